@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::colorized_point::{AgentState, ColorizedPoint};
+use crate::bytes::push_u32;
+use crate::error::DlaError;
+
+// One row per agent in field order: x, y, state, color r, g, b, a, the cell of the sticky neighbor
+// and the hops to the root. Coordinates are cells as on the field, y pointing down. Free agents
+// and roots have no parent, free agents no depth either, which CSV leaves empty and PLY writes
+// as -1
+const COLUMNS: [&str; 10] = ["x", "y", "state", "r", "g", "b", "a", "parent_x", "parent_y", "depth"];
+
+const STATE_FREE: u8 = 0;
+const STATE_STUCK: u8 = 1;
+
+const NO_VALUE: i32 = -1;
+
+#[wasm_bindgen]
+impl DLAField {
+    pub fn export_csv(&self) -> Result<String, DlaError> {
+        let rows = self.agent_rows()?;
+        let mut csv = COLUMNS.join(",");
+        csv.push('\n');
+
+        for (agent, depth) in rows {
+            let color = agent.get_color();
+            let state = match agent.state {
+                AgentState::FREE => "FREE",
+                AgentState::STUCK => "STUCK"
+            };
+            let (parent_x, parent_y) = match agent.sticky_neighbor {
+                Some(neighbor) => (neighbor.x.to_string(), neighbor.y.to_string()),
+                None => (String::new(), String::new())
+            };
+            let depth = depth.map(|depth| depth.to_string()).unwrap_or_default();
+
+            // writing to a String can't fail
+            let _ = writeln!(csv, "{},{},{},{},{},{},{},{},{},{}", agent.get_x(), agent.get_y(), state,
+                color.get_r(), color.get_g(), color.get_b(), color.get_a(), parent_x, parent_y, depth);
+        }
+
+        Ok(csv)
+    }
+
+    // A point cloud with one vertex per agent at (x, y, 0) carrying the CSV columns as vertex
+    // properties, red, green, blue and alpha named the way viewers pick them up for coloring.
+    // State is 0 for free agents and 1 for stuck ones. Binary files are little endian
+    pub fn export_ply(&self, binary: bool) -> Result<Vec<u8>, DlaError> {
+        let rows = self.agent_rows()?;
+
+        let mut header = String::new();
+        let _ = writeln!(header, "ply");
+        let _ = writeln!(header, "format {} 1.0", if binary { "binary_little_endian" } else { "ascii" });
+        let _ = writeln!(header, "comment wasm-rust-dla {}x{} field", self.width, self.height);
+        let _ = writeln!(header, "element vertex {}", rows.len());
+        for property in ["float x", "float y", "float z", "uchar state", "uchar red", "uchar green", "uchar blue",
+            "uchar alpha", "int parent_x", "int parent_y", "int depth"].iter() {
+            let _ = writeln!(header, "property {}", property);
+        }
+        let _ = writeln!(header, "end_header");
+
+        let mut ply = header.into_bytes();
+
+        for (agent, depth) in rows {
+            let color = agent.get_color();
+            let state = match agent.state {
+                AgentState::FREE => STATE_FREE,
+                AgentState::STUCK => STATE_STUCK
+            };
+            let (parent_x, parent_y) = match agent.sticky_neighbor {
+                Some(neighbor) => (neighbor.x as i32, neighbor.y as i32),
+                None => (NO_VALUE, NO_VALUE)
+            };
+            let depth = depth.map(|depth| depth as i32).unwrap_or(NO_VALUE);
+
+            if binary {
+                for coordinate in [agent.get_x() as f32, agent.get_y() as f32, 0.0].iter() {
+                    ply.extend_from_slice(&coordinate.to_le_bytes());
+                }
+                ply.extend_from_slice(&[state, color.get_r(), color.get_g(), color.get_b(), color.get_a()]);
+                for value in [parent_x, parent_y, depth].iter() {
+                    push_u32(&mut ply, *value as u32);
+                }
+            } else {
+                ply.extend_from_slice(format!("{} {} 0 {} {} {} {} {} {} {} {}\n", agent.get_x(), agent.get_y(), state,
+                    color.get_r(), color.get_g(), color.get_b(), color.get_a(), parent_x, parent_y, depth).as_bytes());
+            }
+        }
+
+        Ok(ply)
+    }
+}
+
+impl DLAField {
+    // every agent with its depth, None for free agents
+    fn agent_rows(&self) -> Result<Vec<(ColorizedPoint, Option<usize>)>, DlaError> {
+        let tree = self.aggregate_tree()?;
+        // the tree has the stuck agents in field order
+        let mut depths = tree.nodes.iter().map(|node| node.depth);
+
+        Ok(self.agents.iter()
+            .map(|agent| match agent.state {
+                AgentState::FREE => (*agent, None),
+                AgentState::STUCK => (*agent, depths.next())
+            })
+            .collect())
+    }
+}
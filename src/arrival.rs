@@ -0,0 +1,84 @@
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::cell_grid::CellGrid;
+use crate::colorized_point::AgentState;
+use crate::error::DlaError;
+
+pub const NOT_ARRIVED: i32 = -1;
+
+// The tick each stuck agent arrived at, which the field itself doesn't keep. Agents that were
+// already stuck when recording started count as tick 0, cells nothing stuck to yet stay at -1.
+// Like History it follows the one field it was created from
+#[wasm_bindgen]
+pub struct ArrivalTimes {
+    ticks: CellGrid<i32>,
+    tick: usize
+}
+
+#[wasm_bindgen]
+impl ArrivalTimes {
+    #[wasm_bindgen(constructor)]
+    pub fn new(field: &DLAField) -> ArrivalTimes {
+        let ticks = if field.is_sparse() {
+            CellGrid::new_chunked(field.width, field.height, NOT_ARRIVED)
+        } else {
+            CellGrid::new_dense(field.width, field.height, NOT_ARRIVED)
+        };
+
+        let mut arrivals = ArrivalTimes { ticks, tick: 0 };
+        arrivals.mark_stuck(field);
+
+        arrivals
+    }
+
+    pub fn get_tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn get_arrival(&self, x: usize, y: usize) -> Result<i32, DlaError> {
+        let (width, height) = self.ticks.dimensions();
+        if x >= width || y >= height {
+            return Err(DlaError::InvalidData(format!("({}, {}) is outside of the {}x{} field", x, y, width, height)));
+        }
+
+        Ok(self.ticks.get(x, y))
+    }
+
+    // runs next_state and records whoever stuck during it
+    pub fn step(&mut self, field: &mut DLAField) -> Result<bool, DlaError> {
+        let has_next_state = field.next_state();
+        self.record(field)?;
+
+        Ok(has_next_state)
+    }
+
+    // For fields moved on by something else, History or Autosave say. Call it once after every
+    // tick, agents that stuck in between get the later tick
+    pub fn record(&mut self, field: &DLAField) -> Result<(), DlaError> {
+        if self.ticks.dimensions() != (field.width, field.height) {
+            let (width, height) = self.ticks.dimensions();
+            return Err(DlaError::InvalidData(format!(
+                "arrivals: recorded a {}x{} field, got a {}x{} one", width, height, field.width, field.height)));
+        }
+
+        self.tick += 1;
+        self.mark_stuck(field);
+
+        Ok(())
+    }
+}
+
+impl ArrivalTimes {
+    pub fn ticks(&self) -> &CellGrid<i32> {
+        &self.ticks
+    }
+
+    fn mark_stuck(&mut self, field: &DLAField) {
+        for agent in field.agents.iter().filter(|agent| agent.state == AgentState::STUCK) {
+            if self.ticks.get(agent.get_x(), agent.get_y()) == NOT_ARRIVED {
+                self.ticks.set(agent.get_x(), agent.get_y(), self.tick as i32);
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::error::DlaError;
+
+// Somewhere for autosave snapshots to go, gets the tick the field is at and its snapshot
+pub trait AutosaveSink {
+    fn save(&mut self, tick: usize, snapshot: &[u8]) -> Result<(), DlaError>;
+}
+
+// Steps a field and saves a snapshot every every_ticks ticks and every time every_stuck more
+// agents got stuck since the last save, whichever comes first. A threshold of 0 turns it off
+#[wasm_bindgen]
+pub struct Autosave {
+    every_ticks: usize,
+    every_stuck: usize,
+    sink: Box<dyn AutosaveSink>,
+    // save DLAField::to_compressed rather than to_bytes, from_bytes reads both
+    compressed: bool,
+    tick: usize,
+    last_save_tick: usize,
+    last_save_stuck: usize,
+    saves: usize
+}
+
+impl Autosave {
+    pub fn new(every_ticks: usize, every_stuck: usize, sink: Box<dyn AutosaveSink>) -> Autosave {
+        Autosave {
+            every_ticks,
+            every_stuck,
+            sink,
+            compressed: false,
+            tick: 0,
+            last_save_tick: 0,
+            last_save_stuck: 0,
+            saves: 0
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Autosave {
+    // The callback is called with the snapshot as a Uint8Array and the tick it was taken at,
+    // anything it throws comes back out of step
+    pub fn with_callback(every_ticks: usize, every_stuck: usize, callback: js_sys::Function) -> Autosave {
+        Autosave::new(every_ticks, every_stuck, Box::new(CallbackSink { callback }))
+    }
+
+    pub fn get_tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn get_saves(&self) -> usize {
+        self.saves
+    }
+
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed;
+    }
+
+    // carries the tick count on when a run is picked back up from one of its snapshots, the
+    // field it was restored into counts as just saved so neither threshold fires straight away
+    pub fn set_tick(&mut self, tick: usize, field: &DLAField) {
+        self.tick = tick;
+        self.last_save_tick = tick;
+        self.last_save_stuck = field.occupancy.stuck_count();
+    }
+
+    // runs next_state and saves when one of the thresholds has been reached
+    pub fn step(&mut self, field: &mut DLAField) -> Result<bool, DlaError> {
+        let has_next_state = field.next_state();
+        self.tick += 1;
+
+        let due_by_ticks = self.every_ticks > 0 && self.tick - self.last_save_tick >= self.every_ticks;
+        let due_by_stuck = self.every_stuck > 0 &&
+            field.occupancy.stuck_count().saturating_sub(self.last_save_stuck) >= self.every_stuck;
+
+        if due_by_ticks || due_by_stuck {
+            self.save_now(field)?;
+        }
+
+        Ok(has_next_state)
+    }
+
+    // saves right away, e.g. once the run completes or the page is about to close
+    pub fn save_now(&mut self, field: &DLAField) -> Result<(), DlaError> {
+        let snapshot = if self.compressed { field.to_compressed() } else { field.to_bytes() };
+        self.sink.save(self.tick, &snapshot)?;
+
+        self.last_save_tick = self.tick;
+        self.last_save_stuck = field.occupancy.stuck_count();
+        self.saves += 1;
+
+        Ok(())
+    }
+}
+
+struct CallbackSink {
+    callback: js_sys::Function
+}
+
+impl AutosaveSink for CallbackSink {
+    fn save(&mut self, tick: usize, snapshot: &[u8]) -> Result<(), DlaError> {
+        let snapshot = js_sys::Uint8Array::from(snapshot);
+
+        self.callback.call2(&JsValue::NULL, &snapshot, &JsValue::from(tick as u32))
+            .map(|_| ())
+            .map_err(|err| DlaError::Autosave(format!("callback threw {:?}", err)))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::files::{RotatingFiles, latest_autosave};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod files {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::collections::VecDeque;
+
+    use super::{Autosave, AutosaveSink};
+    use crate::DLAField;
+    use crate::error::DlaError;
+
+    const EXTENSION: &str = "dlaf";
+
+    // Writes every snapshot to <dir>/<prefix>-<tick>.dlaf and keeps only the newest `keep` of the
+    // ones it wrote. Snapshots go to a temporary file first and are renamed into place, so a crash
+    // mid write never leaves a torn file behind
+    pub struct RotatingFiles {
+        dir: PathBuf,
+        prefix: String,
+        keep: usize,
+        written: VecDeque<PathBuf>
+    }
+
+    impl RotatingFiles {
+        pub fn new<P: Into<PathBuf>>(dir: P, prefix: &str, keep: usize) -> RotatingFiles {
+            RotatingFiles { dir: dir.into(), prefix: prefix.to_string(), keep: keep.max(1), written: VecDeque::new() }
+        }
+    }
+
+    impl AutosaveSink for RotatingFiles {
+        fn save(&mut self, tick: usize, snapshot: &[u8]) -> Result<(), DlaError> {
+            let path = self.dir.join(format!("{}-{:010}.{}", self.prefix, tick, EXTENSION));
+            let tmp_path = path.with_extension("tmp");
+
+            fs::create_dir_all(&self.dir)
+                .and_then(|_| fs::write(&tmp_path, snapshot))
+                .and_then(|_| fs::rename(&tmp_path, &path))
+                .map_err(|err| DlaError::Autosave(format!("{}: {}", path.display(), err)))?;
+
+            self.written.push_back(path);
+
+            while self.written.len() > self.keep {
+                let oldest = self.written.pop_front().unwrap();
+                fs::remove_file(&oldest)
+                    .map_err(|err| DlaError::Autosave(format!("{}: {}", oldest.display(), err)))?;
+            }
+
+            Ok(())
+        }
+    }
+
+    impl Autosave {
+        pub fn to_files<P: Into<PathBuf>>(
+            every_ticks: usize,
+            every_stuck: usize,
+            dir: P,
+            prefix: &str,
+            keep: usize
+        ) -> Autosave {
+            Autosave::new(every_ticks, every_stuck, Box::new(RotatingFiles::new(dir, prefix, keep)))
+        }
+    }
+
+    // Loads the newest snapshot RotatingFiles left in dir for the prefix, along with its tick, to
+    // pick a run back up after a crash
+    pub fn latest_autosave<P: Into<PathBuf>>(dir: P, prefix: &str) -> Result<Option<(usize, DLAField)>, DlaError> {
+        let dir = dir.into();
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(None)
+        };
+
+        let name_prefix = format!("{}-", prefix);
+        let latest = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != EXTENSION {
+                    return None;
+                }
+
+                let tick = path.file_stem()?.to_str()?.strip_prefix(&name_prefix)?.parse::<usize>().ok()?;
+                Some((tick, path))
+            })
+            .max_by_key(|(tick, _)| *tick);
+
+        match latest {
+            None => Ok(None),
+            Some((tick, path)) => {
+                let snapshot = fs::read(&path)
+                    .map_err(|err| DlaError::Autosave(format!("{}: {}", path.display(), err)))?;
+
+                Ok(Some((tick, DLAField::from_bytes(&snapshot)?)))
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::convert::TryInto;
+
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+
+use crate::MAX_DENSE_CELLS;
+use crate::bytes::crc32;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const COLOR_TYPE_GRAY: u8 = 0;
+const COLOR_TYPE_RGB: u8 = 2;
+const COLOR_TYPE_PALETTE: u8 = 3;
+const COLOR_TYPE_GRAY_ALPHA: u8 = 4;
+const COLOR_TYPE_RGBA: u8 = 6;
+
+const PBM_BLACK: [u8; 4] = [0, 0, 0, 255];
+const PBM_WHITE: [u8; 4] = [255, 255, 255, 255];
+
+// A decoded image, RGBA pixels row by row with the top row first
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 4]>
+}
+
+impl Bitmap {
+    // PNG or PBM, told apart by their first bytes
+    pub fn decode(bytes: &[u8]) -> Result<Bitmap, String> {
+        if bytes.starts_with(&PNG_SIGNATURE) {
+            decode_png(bytes)
+        } else if bytes.starts_with(b"P1") || bytes.starts_with(b"P4") {
+            decode_pbm(bytes)
+        } else {
+            Err("bitmap: neither a PNG nor a PBM (P1 or P4) image".to_string())
+        }
+    }
+}
+
+// What IHDR says about the image
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8
+}
+
+impl PngHeader {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            COLOR_TYPE_RGB => 3,
+            COLOR_TYPE_GRAY_ALPHA => 2,
+            COLOR_TYPE_RGBA => 4,
+            _ => 1
+        }
+    }
+
+    fn row_len(&self) -> Option<usize> {
+        let bits = self.width.checked_mul(self.channels() * self.bit_depth as usize)?;
+        Some(bits.div_ceil(8))
+    }
+}
+
+// Every color type and bit depth of the spec, but no interlacing. 16 bit samples are cut down to
+// their high byte and tRNS makes the listed color or palette entries transparent
+fn decode_png(bytes: &[u8]) -> Result<Bitmap, String> {
+    let mut offset = PNG_SIGNATURE.len();
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = vec![];
+    let mut transparency: Vec<u8> = vec![];
+    let mut image_data = vec![];
+
+    loop {
+        if bytes.len() < offset + 12 {
+            return Err("png: ends before the IEND chunk".to_string());
+        }
+
+        let len = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let chunk_type = &bytes[offset + 4..offset + 8];
+        let name = String::from_utf8_lossy(chunk_type).into_owned();
+
+        if bytes.len() - offset - 12 < len {
+            return Err(format!("png: {} chunk runs past the end of the file", name));
+        }
+
+        let data = &bytes[offset + 8..offset + 8 + len];
+        let crc = u32::from_be_bytes(bytes[offset + 8 + len..offset + 12 + len].try_into().unwrap());
+        if crc32(&bytes[offset + 4..offset + 8 + len]) != crc {
+            return Err(format!("png: {} chunk fails its CRC", name));
+        }
+        offset += 12 + len;
+
+        match chunk_type {
+            b"IHDR" => {
+                if data.len() != 13 {
+                    return Err("png: IHDR chunk has the wrong length".to_string());
+                }
+
+                let parsed = PngHeader {
+                    width: u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize,
+                    height: u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize,
+                    bit_depth: data[8],
+                    color_type: data[9]
+                };
+
+                let supported = match parsed.color_type {
+                    COLOR_TYPE_GRAY => [1, 2, 4, 8, 16].contains(&parsed.bit_depth),
+                    COLOR_TYPE_PALETTE => [1, 2, 4, 8].contains(&parsed.bit_depth),
+                    COLOR_TYPE_RGB | COLOR_TYPE_GRAY_ALPHA | COLOR_TYPE_RGBA => [8, 16].contains(&parsed.bit_depth),
+                    _ => false
+                };
+                if !supported {
+                    return Err(format!("png: color type {} at bit depth {} is not valid",
+                        parsed.color_type, parsed.bit_depth));
+                }
+                if data[12] != 0 {
+                    return Err("png: interlaced images are not supported".to_string());
+                }
+                // before anything gets inflated, a few bytes of zlib can claim gigabytes of pixels
+                check_size("png", parsed.width, parsed.height)?;
+
+                header = Some(parsed);
+            },
+            b"PLTE" => {
+                if !data.len().is_multiple_of(3) || data.len() > 256 * 3 {
+                    return Err(format!("png: PLTE length {} is not 1 to 256 rgb entries", data.len()));
+                }
+
+                palette = data.chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect();
+            },
+            b"tRNS" => transparency = data.to_vec(),
+            b"IDAT" => image_data.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or("png: no IHDR chunk")?;
+    let row_len = header.row_len().ok_or("png: image too large")?;
+    let raw_len = row_len.checked_add(1).and_then(|len| len.checked_mul(header.height)).ok_or("png: image too large")?;
+
+    let raw = decompress_to_vec_zlib_with_limit(&image_data, raw_len)
+        .map_err(|err| format!("png: image data doesn't inflate, {:?}", err.status))?;
+    if raw.len() != raw_len {
+        return Err(format!("png: image data holds {} bytes, expected {}", raw.len(), raw_len));
+    }
+
+    // for palettes tRNS holds the alpha of the first few entries
+    if header.color_type == COLOR_TYPE_PALETTE {
+        for (color, alpha) in palette.iter_mut().zip(transparency.iter()) {
+            color[3] = *alpha;
+        }
+    }
+
+    let rows = unfilter(&header, row_len, &raw)?;
+    let mut pixels = Vec::with_capacity(header.width * header.height);
+
+    for row in rows.chunks(row_len.max(1)).take(header.height) {
+        for x in 0..header.width {
+            pixels.push(png_pixel(&header, row, x, &palette, &transparency)?);
+        }
+    }
+
+    Ok(Bitmap { width: header.width, height: header.height, pixels })
+}
+
+// Undoes the per row filters, each row is compared with the one above and the pixel to the left
+fn unfilter(header: &PngHeader, row_len: usize, raw: &[u8]) -> Result<Vec<u8>, String> {
+    // distance to the same byte of the pixel on the left, at least one byte
+    let stride = (header.channels() * header.bit_depth as usize).div_ceil(8);
+    let mut rows = vec![0; row_len * header.height];
+
+    for y in 0..header.height {
+        let filter = raw[y * (row_len + 1)];
+        let line = &raw[y * (row_len + 1) + 1..(y + 1) * (row_len + 1)];
+
+        for x in 0..row_len {
+            let left = if x >= stride { rows[y * row_len + x - stride] } else { 0 };
+            let up = if y > 0 { rows[(y - 1) * row_len + x] } else { 0 };
+            let up_left = if y > 0 && x >= stride { rows[(y - 1) * row_len + x - stride] } else { 0 };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                other => return Err(format!("png: row {} uses unknown filter {}", y, other))
+            };
+
+            rows[y * row_len + x] = line[x].wrapping_add(predicted);
+        }
+    }
+
+    Ok(rows)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = ((estimate - left as i16).abs(), (estimate - up as i16).abs(), (estimate - up_left as i16).abs());
+
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn png_pixel(header: &PngHeader, row: &[u8], x: usize, palette: &[[u8; 4]], transparency: &[u8]) -> Result<[u8; 4], String> {
+    let depth = header.bit_depth as usize;
+    // sample n of the pixel, full 16 bit value for comparing with tRNS
+    let sample = |n: usize| -> u16 {
+        let ndx = x * header.channels() + n;
+        match depth {
+            16 => u16::from_be_bytes([row[ndx * 2], row[ndx * 2 + 1]]),
+            8 => row[ndx] as u16,
+            _ => {
+                let bit = ndx * depth;
+                ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8) as u16
+            }
+        }
+    };
+    // down to 8 bits
+    let scale = |value: u16| -> u8 {
+        match depth {
+            16 => (value >> 8) as u8,
+            8 => value as u8,
+            _ => (value as usize * 255 / ((1 << depth) - 1)) as u8
+        }
+    };
+    let keyed = |values: &[u16]| {
+        transparency.len() == values.len() * 2 &&
+            values.iter().enumerate().all(|(n, value)| u16::from_be_bytes([transparency[n * 2], transparency[n * 2 + 1]]) == *value)
+    };
+
+    Ok(match header.color_type {
+        COLOR_TYPE_GRAY => {
+            let gray = sample(0);
+            [scale(gray), scale(gray), scale(gray), if keyed(&[gray]) { 0 } else { 255 }]
+        },
+        COLOR_TYPE_RGB => {
+            let rgb = [sample(0), sample(1), sample(2)];
+            [scale(rgb[0]), scale(rgb[1]), scale(rgb[2]), if keyed(&rgb) { 0 } else { 255 }]
+        },
+        COLOR_TYPE_PALETTE => {
+            let ndx = sample(0) as usize;
+            *palette.get(ndx).ok_or_else(|| format!("png: palette index {} past the {} colors of PLTE", ndx, palette.len()))?
+        },
+        COLOR_TYPE_GRAY_ALPHA => {
+            let gray = scale(sample(0));
+            [gray, gray, gray, scale(sample(1))]
+        },
+        _ => [scale(sample(0)), scale(sample(1)), scale(sample(2)), scale(sample(3))]
+    })
+}
+
+// P1 is 0s and 1s as text, P4 packs 8 pixels to a byte with each row starting on a new byte.
+// 1 is black either way
+fn decode_pbm(bytes: &[u8]) -> Result<Bitmap, String> {
+    let binary = bytes[1] == b'4';
+    let mut offset = 2;
+
+    let width = pbm_number(bytes, &mut offset, "width")?;
+    let height = pbm_number(bytes, &mut offset, "height")?;
+    let num_pixels = check_size("pbm", width, height)?;
+
+    let mut pixels = Vec::with_capacity(num_pixels.min(bytes.len() * 8));
+
+    if binary {
+        // exactly one whitespace byte between the header and the pixels
+        offset += 1;
+        let row_len = width.div_ceil(8);
+        let data = bytes.get(offset..).unwrap_or(&[]);
+
+        if data.len() < row_len * height {
+            return Err(format!("pbm: holds {} bytes of pixels, expected {}", data.len(), row_len * height));
+        }
+
+        for row in data.chunks(row_len.max(1)).take(height) {
+            for x in 0..width {
+                let black = row[x / 8] & (0x80 >> (x % 8)) != 0;
+                pixels.push(if black { PBM_BLACK } else { PBM_WHITE });
+            }
+        }
+    } else {
+        while pixels.len() < num_pixels {
+            skip_pbm_whitespace(bytes, &mut offset);
+
+            match bytes.get(offset) {
+                Some(b'0') => pixels.push(PBM_WHITE),
+                Some(b'1') => pixels.push(PBM_BLACK),
+                Some(other) => return Err(format!("pbm: unexpected '{}' at byte {}", *other as char, offset)),
+                None => return Err(format!("pbm: holds {} pixels, expected {}", pixels.len(), num_pixels))
+            }
+            offset += 1;
+        }
+    }
+
+    Ok(Bitmap { width, height, pixels })
+}
+
+// an image becomes a dense field with a cell per pixel, so it has to fit in one
+fn check_size(format: &str, width: usize, height: usize) -> Result<usize, String> {
+    match width.checked_mul(height) {
+        Some(num_pixels) if num_pixels <= MAX_DENSE_CELLS => Ok(num_pixels),
+        _ => Err(format!("{}: {}x{} pixels is too large, at most {} cells fit in a field",
+            format, width, height, MAX_DENSE_CELLS))
+    }
+}
+
+fn skip_pbm_whitespace(bytes: &[u8], offset: &mut usize) {
+    while let Some(&byte) = bytes.get(*offset) {
+        match byte {
+            b'#' => {
+                // comments run to the end of the line
+                while bytes.get(*offset).is_some_and(|&byte| byte != b'\n') {
+                    *offset += 1;
+                }
+            },
+            byte if byte.is_ascii_whitespace() => *offset += 1,
+            _ => return
+        }
+    }
+}
+
+fn pbm_number(bytes: &[u8], offset: &mut usize, what: &str) -> Result<usize, String> {
+    skip_pbm_whitespace(bytes, offset);
+
+    let start = *offset;
+    while bytes.get(*offset).is_some_and(|byte| byte.is_ascii_digit()) {
+        *offset += 1;
+    }
+
+    // only ascii digits were taken, so this is always utf8
+    String::from_utf8_lossy(&bytes[start..*offset])
+        .parse()
+        .map_err(|_| format!("pbm: {} is missing or not a number", what))
+}
+
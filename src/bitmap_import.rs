@@ -0,0 +1,127 @@
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::bitmap::Bitmap;
+use crate::colorized_point::{AgentState, Color, ColorizedPoint};
+use crate::rng::Rng;
+use crate::error::DlaError;
+
+// What a pixel of an imported bitmap turns into
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitmapCell {
+    EMPTY,
+    WALL,
+    FREE,
+    STUCK
+}
+
+// Colors to cell states for from_bitmap. Each pixel takes the state of the closest color listed,
+// so anti-aliased edges still land somewhere sensible, and pixels that are more than half
+// transparent are empty. The defaults are white for empty cells, black for walls, red for stuck
+// seeds and green for free walkers. PBM images only have black and white
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitmapLegend {
+    colors: Vec<([u8; 3], BitmapCell)>
+}
+
+impl Default for BitmapLegend {
+    fn default() -> BitmapLegend {
+        BitmapLegend {
+            colors: vec![
+                ([255, 255, 255], BitmapCell::EMPTY),
+                ([0, 0, 0], BitmapCell::WALL),
+                ([255, 0, 0], BitmapCell::STUCK),
+                ([0, 255, 0], BitmapCell::FREE)
+            ]
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl BitmapLegend {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> BitmapLegend {
+        BitmapLegend::default()
+    }
+
+    // adds a color or changes the state of one already listed
+    pub fn set_color(&mut self, r: u8, g: u8, b: u8, cell: BitmapCell) {
+        match self.colors.iter_mut().find(|(color, _)| *color == [r, g, b]) {
+            Some(entry) => entry.1 = cell,
+            None => self.colors.push(([r, g, b], cell))
+        }
+    }
+
+    // drops every color, including the defaults
+    pub fn clear(&mut self) {
+        self.colors.clear();
+    }
+}
+
+impl BitmapLegend {
+    fn cell_for(&self, pixel: [u8; 4]) -> BitmapCell {
+        if pixel[3] < 128 {
+            return BitmapCell::EMPTY;
+        }
+
+        let distance = |color: &[u8; 3]| -> u32 {
+            (0..3).map(|channel| (color[channel] as i32 - pixel[channel] as i32).pow(2) as u32).sum()
+        };
+
+        self.colors.iter()
+            .min_by_key(|(color, _)| distance(color))
+            .map_or(BitmapCell::EMPTY, |&(_, cell)| cell)
+    }
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    // A field the size of a PNG or PBM image with one cell per pixel, set up after legend. Agents
+    // are listed row by row from the top, stuck seeds are roots
+    pub fn from_bitmap(canvas_id: String, bytes: &[u8], legend: &BitmapLegend) -> Result<DLAField, DlaError> {
+        DLAField::with_bitmap(canvas_id, bytes, legend, Rng::from_entropy())
+    }
+
+    // same as from_bitmap, but the walks are reproducible for a given seed. A ReplayConfig can't
+    // describe a bitmap, replays of these fields come from Replay::record_field
+    pub fn from_bitmap_seeded(canvas_id: String, bytes: &[u8], legend: &BitmapLegend, seed: u32) -> Result<DLAField, DlaError> {
+        DLAField::with_bitmap(canvas_id, bytes, legend, Rng::new(seed as u64))
+    }
+}
+
+impl DLAField {
+    fn with_bitmap(canvas_id: String, bytes: &[u8], legend: &BitmapLegend, rng: Rng) -> Result<DLAField, DlaError> {
+        let bitmap = Bitmap::decode(bytes)?;
+        if bitmap.width == 0 || bitmap.height == 0 {
+            return Err(DlaError::InvalidData(format!("bitmap: a {}x{} image has no cells", bitmap.width, bitmap.height)));
+        }
+
+        let mut agents = vec![];
+        let mut walls = vec![];
+
+        for (ndx, pixel) in bitmap.pixels.iter().enumerate() {
+            let (x, y) = (ndx % bitmap.width, ndx / bitmap.width);
+
+            let state = match legend.cell_for(*pixel) {
+                BitmapCell::EMPTY => continue,
+                BitmapCell::WALL => {
+                    walls.push((x, y));
+                    continue;
+                },
+                BitmapCell::FREE => AgentState::FREE,
+                BitmapCell::STUCK => AgentState::STUCK
+            };
+
+            let mut agent = ColorizedPoint::new(x, y, Color::new(255, 0, 0, 100), None);
+            agent.state = state;
+            agents.push(agent);
+        }
+
+        let mut field = DLAField::from_agents(canvas_id, bitmap.width, bitmap.height, false, agents, rng)?;
+        field.add_walls(&walls)?;
+
+        Ok(field)
+    }
+}
@@ -0,0 +1,171 @@
+// Little endian helpers for the binary formats the field reads and writes
+
+pub fn push_u8(bytes: &mut Vec<u8>, value: u8) {
+    bytes.push(value);
+}
+
+pub fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+// LEB128, 7 bits per byte with the high bit set on every byte but the last
+pub fn push_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+// length prefixed utf8
+pub fn push_str(bytes: &mut Vec<u8>, value: &str) {
+    push_u32(bytes, value.len() as u32);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+// Reads values back in the order they were pushed. Errors name what was being read so a
+// truncated or corrupt buffer can be tracked down
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> ByteReader<'a> {
+        ByteReader { bytes, offset: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    pub fn read_bytes(&mut self, len: usize, what: &str) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.offset < len {
+            return Err(format!("{}: unexpected end of data at byte {}", what, self.offset));
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self, what: &str) -> Result<u8, String> {
+        Ok(self.read_bytes(1, what)?[0])
+    }
+
+    pub fn read_u16(&mut self, what: &str) -> Result<u16, String> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.read_bytes(2, what)?);
+
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn read_u32(&mut self, what: &str) -> Result<u32, String> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.read_bytes(4, what)?);
+
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub fn read_u64(&mut self, what: &str) -> Result<u64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.read_bytes(8, what)?);
+
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub fn read_varint(&mut self, what: &str) -> Result<u64, String> {
+        let mut value = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8(what)?;
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(format!("{}: varint longer than 64 bits at byte {}", what, self.offset))
+    }
+
+    pub fn read_str(&mut self, what: &str) -> Result<String, String> {
+        let len = self.read_u32(what)? as usize;
+        let bytes = self.read_bytes(len, what)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| format!("{}: not valid utf8", what))
+    }
+}
+
+// CRC-32 as used by PNG and zip (IEEE, reflected, polynomial 0xedb88320)
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        *entry = (0..8).fold(n as u32, |c, _| if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 });
+    }
+
+    !bytes.iter().fold(!0u32, |crc, &byte| table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+// 64 bit FNV-1a, enough to tell two snapshots apart without keeping them around
+pub fn digest(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// standard base64 with = padding, for binary data inside JSON
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for group in bytes.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (ndx, &byte)| bits | (byte as u32) << (16 - ndx * 8));
+
+        for ndx in 0..4 {
+            if ndx <= group.len() {
+                encoded.push(BASE64_ALPHABET[(bits >> (18 - ndx * 6)) as usize & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+pub fn base64_decode(encoded: &str, what: &str) -> Result<Vec<u8>, String> {
+    let encoded = encoded.as_bytes();
+    if !encoded.len().is_multiple_of(4) {
+        return Err(format!("{}: base64 length {} is not a multiple of 4", what, encoded.len()));
+    }
+
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+
+    for (group_ndx, group) in encoded.chunks(4).enumerate() {
+        let last = group_ndx + 1 == encoded.len() / 4;
+        let padding = group.iter().rev().take_while(|&&symbol| symbol == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return Err(format!("{}: misplaced base64 padding", what));
+        }
+
+        let mut bits = 0u32;
+        for (ndx, &symbol) in group[..4 - padding].iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|&letter| letter == symbol)
+                .ok_or_else(|| format!("{}: '{}' is not a base64 character", what, symbol as char))?;
+            bits |= (value as u32) << (18 - ndx * 6);
+        }
+
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeSet;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
+
+use crate::DLAField;
+use crate::change_set::ChangeSet;
+use crate::field_position::FieldState;
+use crate::palette::Palette;
+use crate::error::DlaError;
+
+// changed cells are pushed to the canvas in blocks of TILE_SIZE x TILE_SIZE pixels
+const TILE_SIZE: usize = 32;
+
+pub fn color_for_state(state: FieldState) -> [u8; 4] {
+    Palette::default().color_for(state)
+}
+
+// Holds on to the 2d context of a canvas so it only has to be looked up once, and keeps an RGBA
+// copy of what is on the canvas. draw paints a whole field, after that draw_changes only touches
+// the cells listed in a change set from DLAField::take_changes:
+//
+//   field.set_track_changes(true)
+//   renderer.draw(field)
+//   // every frame
+//   field.next_state()
+//   renderer.draw_changes(field.take_changes())
+//
+// Only the tiles holding a changed cell are put on the canvas, so a frame costs about the same
+// however large the field is
+#[wasm_bindgen]
+pub struct CanvasRenderer {
+    context: CanvasRenderingContext2d,
+    width: usize,
+    height: usize,
+    // row by row, top row first
+    pixels: Vec<u8>
+}
+
+#[wasm_bindgen]
+impl CanvasRenderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas_id: String) -> Result<CanvasRenderer, DlaError> {
+        let document = web_sys::window()
+            .ok_or(DlaError::NoWindow)?
+            .document()
+            .ok_or(DlaError::NoDocument)?;
+
+        let canvas: HtmlCanvasElement = document
+            .get_element_by_id(&canvas_id)
+            .ok_or_else(|| DlaError::CanvasNotFound(canvas_id.clone()))?
+            .dyn_into::<HtmlCanvasElement>()
+            .map_err(|_| DlaError::NotACanvas(canvas_id.clone()))?;
+
+        let context = canvas
+            .get_context("2d")
+            .ok()
+            .flatten()
+            .and_then(|context| context.dyn_into::<CanvasRenderingContext2d>().ok())
+            .ok_or_else(|| DlaError::NoContext(canvas_id.clone()))?;
+
+        Ok(CanvasRenderer {
+            context,
+            width: 0,
+            height: 0,
+            pixels: vec![]
+        })
+    }
+
+    // repaints every cell of the field, needed once before draw_changes and whenever the renderer
+    // is pointed at a different field
+    pub fn draw(&mut self, dla_field: &DLAField) -> Result<(), DlaError> {
+        self.width = dla_field.get_width();
+        self.height = dla_field.get_height();
+
+        let empty = color_for_state(FieldState::EMPTY);
+        self.pixels = empty.iter().copied().cycle().take(self.width * self.height * 4).collect();
+
+        // sparse fields only hand back the tiles they allocated, everything else is empty
+        for (x, y, position) in dla_field.position_hash.cells() {
+            self.set_pixel(x, y, position.state);
+        }
+
+        self.put_region(0, 0, self.width, self.height)
+    }
+
+    // Paints the cells listed in changes and pushes the tiles they fall in. Cells outside the
+    // field drawn last are skipped
+    pub fn draw_changes(&mut self, changes: &ChangeSet) -> Result<(), DlaError> {
+        let mut dirty_tiles = BTreeSet::new();
+
+        for (x, y, state) in changes.cells() {
+            if x >= self.width || y >= self.height {
+                continue;
+            }
+
+            self.set_pixel(x, y, state);
+            dirty_tiles.insert((x / TILE_SIZE, y / TILE_SIZE));
+        }
+
+        for (tile_x, tile_y) in dirty_tiles {
+            let (x, y) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+
+            self.put_region(x, y, TILE_SIZE.min(self.width - x), TILE_SIZE.min(self.height - y))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CanvasRenderer {
+    fn set_pixel(&mut self, x: usize, y: usize, state: FieldState) {
+        let offset = (y * self.width + x) * 4;
+        self.pixels[offset..offset + 4].copy_from_slice(&color_for_state(state));
+    }
+
+    // copies a block of the cached pixels into an ImageData of its own size and puts it in place
+    fn put_region(&self, x: usize, y: usize, width: usize, height: usize) -> Result<(), DlaError> {
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let mut region = Vec::with_capacity(width * height * 4);
+        for row in y..y + height {
+            let start = (row * self.width + x) * 4;
+            region.extend_from_slice(&self.pixels[start..start + width * 4]);
+        }
+
+        let image_data = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&mut region[..]),
+            width as u32,
+            height as u32
+        ).map_err(|err| DlaError::Canvas(format!("{:?}", err)))?;
+
+        self.context.put_image_data(&image_data, x as f64, y as f64)
+            .map_err(|err| DlaError::Canvas(format!("{:?}", err)))
+    }
+}
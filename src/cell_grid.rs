@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+// chunked grids allocate square tiles of CHUNK_SIZE x CHUNK_SIZE cells
+pub const CHUNK_SIZE: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk<T> {
+    cells: Vec<T>,
+    // number of cells holding something other than the grid's empty value
+    filled: usize
+}
+
+// Storage for one value per cell of the field. Dense grids preallocate width * height cells laid
+// out column by column, the same way as DLAField::get_ndx. Chunked grids only allocate the tiles
+// that hold something other than the empty value and drop them again once they empty out, so
+// memory follows the agents rather than the size of the plane. Both have fixed dimensions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CellGrid<T> {
+    Dense {
+        width: usize,
+        height: usize,
+        empty: T,
+        cells: Vec<T>
+    },
+    Chunked {
+        width: usize,
+        height: usize,
+        empty: T,
+        chunks: HashMap<(usize, usize), Chunk<T>>
+    }
+}
+
+impl<T: Copy + PartialEq> CellGrid<T> {
+    pub fn new_dense(width: usize, height: usize, empty: T) -> CellGrid<T> {
+        CellGrid::Dense {
+            width,
+            height,
+            empty,
+            cells: vec![empty; width * height]
+        }
+    }
+
+    pub fn new_chunked(width: usize, height: usize, empty: T) -> CellGrid<T> {
+        CellGrid::Chunked {
+            width,
+            height,
+            empty,
+            chunks: HashMap::new()
+        }
+    }
+
+    // a grid with the same backend and dimensions as this one with every cell empty
+    pub fn empty_like(&self) -> CellGrid<T> {
+        match self {
+            CellGrid::Dense { width, height, empty, .. } =>
+                CellGrid::new_dense(*width, *height, *empty),
+            CellGrid::Chunked { width, height, empty, .. } =>
+                CellGrid::new_chunked(*width, *height, *empty)
+        }
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        match self {
+            CellGrid::Dense { width, height, .. } => (*width, *height),
+            CellGrid::Chunked { width, height, .. } => (*width, *height)
+        }
+    }
+
+    pub fn is_chunked(&self) -> bool {
+        match self {
+            CellGrid::Dense { .. } => false,
+            CellGrid::Chunked { .. } => true
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> T {
+        match self {
+            CellGrid::Dense { height, cells, .. } => cells[x * *height + y],
+            CellGrid::Chunked { empty, chunks, .. } => {
+                match chunks.get(&(x / CHUNK_SIZE, y / CHUNK_SIZE)) {
+                    None => *empty,
+                    Some(chunk) => chunk.cells[CellGrid::<T>::chunk_ndx(x, y)]
+                }
+            }
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: T) {
+        match self {
+            CellGrid::Dense { height, cells, .. } => {
+                cells[x * *height + y] = value;
+            },
+            CellGrid::Chunked { empty, chunks, .. } => {
+                let key = (x / CHUNK_SIZE, y / CHUNK_SIZE);
+                let ndx = CellGrid::<T>::chunk_ndx(x, y);
+                let empty = *empty;
+
+                let chunk = match chunks.entry(key) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        if value == empty {
+                            return; // nothing to clear in a chunk that was never allocated
+                        }
+
+                        entry.insert(Chunk {
+                            cells: vec![empty; CHUNK_SIZE * CHUNK_SIZE],
+                            filled: 0
+                        })
+                    }
+                };
+
+                let was_empty = chunk.cells[ndx] == empty;
+                chunk.cells[ndx] = value;
+
+                match (was_empty, value == empty) {
+                    (true, false) => chunk.filled += 1,
+                    (false, true) => chunk.filled -= 1,
+                    _ => {}
+                }
+
+                if chunk.filled == 0 {
+                    chunks.remove(&key);
+                }
+            }
+        }
+    }
+
+    // every allocated cell that lies inside the field, chunked grids skip unallocated tiles
+    pub fn cells(&self) -> Box<dyn Iterator<Item = (usize, usize, T)> + '_> {
+        match self {
+            CellGrid::Dense { width, height, cells, .. } => {
+                let (width, height) = (*width, *height);
+
+                Box::new((0..width).flat_map(move |x| {
+                    (0..height).map(move |y| (x, y, cells[x * height + y]))
+                }))
+            },
+            CellGrid::Chunked { width, height, chunks, .. } => {
+                let (width, height) = (*width, *height);
+
+                Box::new(chunks.iter().flat_map(move |(&(chunk_x, chunk_y), chunk)| {
+                    chunk.cells.iter().enumerate().filter_map(move |(ndx, value)| {
+                        let x = chunk_x * CHUNK_SIZE + ndx / CHUNK_SIZE;
+                        let y = chunk_y * CHUNK_SIZE + ndx % CHUNK_SIZE;
+
+                        if x < width && y < height { Some((x, y, *value)) } else { None }
+                    })
+                }))
+            }
+        }
+    }
+
+    // number of cells currently backed by memory
+    pub fn allocated_len(&self) -> usize {
+        match self {
+            CellGrid::Dense { cells, .. } => cells.len(),
+            CellGrid::Chunked { chunks, .. } => chunks.len() * CHUNK_SIZE * CHUNK_SIZE
+        }
+    }
+
+    // raw access for JS reading straight out of wasm memory, only dense grids are contiguous
+    pub fn as_ptr(&self) -> Option<*const T> {
+        match self {
+            CellGrid::Dense { cells, .. } => Some(cells.as_ptr()),
+            CellGrid::Chunked { .. } => None
+        }
+    }
+
+    fn chunk_ndx(x: usize, y: usize) -> usize {
+        (x % CHUNK_SIZE) * CHUNK_SIZE + y % CHUNK_SIZE
+    }
+}
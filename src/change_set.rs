@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::colorized_point::StickyNeighbor;
+use crate::field_position::FieldState;
+
+// marks a stuck cell with no parent (a root) in the flattened parent arrays
+pub const NO_PARENT: u32 = u32::MAX;
+
+// the state a changed cell was left in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellChange {
+    Empty,
+    Occupied,
+    Stuck(Option<StickyNeighbor>),
+    Wall
+}
+
+// What the field recorded since the last take_changes, keyed by cell so a later change to a cell
+// replaces an earlier one. A cell vacated by one walker and entered by another, in the same tick
+// or a later one, ends up as a single occupied entry
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangeLog {
+    cells: BTreeMap<(usize, usize), CellChange>
+}
+
+impl ChangeLog {
+    pub fn record_occupied(&mut self, x: usize, y: usize) {
+        self.cells.insert((x, y), CellChange::Occupied);
+    }
+
+    pub fn record_vacated(&mut self, x: usize, y: usize) {
+        self.cells.insert((x, y), CellChange::Empty);
+    }
+
+    pub fn record_stuck(&mut self, x: usize, y: usize, parent: Option<StickyNeighbor>) {
+        self.cells.insert((x, y), CellChange::Stuck(parent));
+    }
+
+    pub fn record_wall(&mut self, x: usize, y: usize) {
+        self.cells.insert((x, y), CellChange::Wall);
+    }
+
+    // splits the cells up by the state they ended in, column by column and top row first
+    pub fn into_change_set(self) -> ChangeSet {
+        let mut change_set = ChangeSet::default();
+
+        for ((x, y), change) in self.cells {
+            let (x, y) = (x as u32, y as u32);
+
+            match change {
+                CellChange::Empty => change_set.vacated.extend_from_slice(&[x, y]),
+                CellChange::Occupied => change_set.occupied.extend_from_slice(&[x, y]),
+                CellChange::Wall => change_set.walls.extend_from_slice(&[x, y]),
+                CellChange::Stuck(parent) => {
+                    change_set.stuck.extend_from_slice(&[x, y]);
+
+                    match parent {
+                        None => change_set.stuck_parents.extend_from_slice(&[NO_PARENT, NO_PARENT]),
+                        Some(parent) => change_set.stuck_parents.extend_from_slice(&[parent.x as u32, parent.y as u32])
+                    }
+                }
+            }
+        }
+
+        change_set
+    }
+}
+
+// Cells that changed during one or more calls to next_state, each listed once under the state it
+// is in now. Coordinates are flattened into [x0, y0, x1, y1, ...] so they cross over to JS as a
+// single Uint32Array each. The take_ methods hand their array over rather than copying it, so
+// each one returns an empty array the second time around
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangeSet {
+    occupied: Vec<u32>,
+    vacated: Vec<u32>,
+    stuck: Vec<u32>,
+    stuck_parents: Vec<u32>,
+    walls: Vec<u32>
+}
+
+impl ChangeSet {
+    // every listed cell with the state it is in now
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize, FieldState)> + '_ {
+        pairs(&self.vacated, FieldState::EMPTY)
+            .chain(pairs(&self.occupied, FieldState::OCCUPIED))
+            .chain(pairs(&self.stuck, FieldState::STUCK))
+            .chain(pairs(&self.walls, FieldState::WALL))
+    }
+}
+
+#[wasm_bindgen]
+impl ChangeSet {
+    // [x, y] pairs of cells that now hold a free agent
+    pub fn take_occupied(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.occupied)
+    }
+
+    // [x, y] pairs of cells that are now empty
+    pub fn take_vacated(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.vacated)
+    }
+
+    // [x, y] pairs of cells whose agent is now stuck
+    pub fn take_stuck(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.stuck)
+    }
+
+    // [parent_x, parent_y] pairs lined up with take_stuck, roots are [NO_PARENT, NO_PARENT]
+    pub fn take_stuck_parents(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.stuck_parents)
+    }
+
+    // [x, y] pairs of walls, they never change so these only come with the first change set
+    pub fn take_walls(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.walls)
+    }
+
+    pub fn get_no_parent() -> u32 {
+        NO_PARENT
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.occupied.is_empty() && self.vacated.is_empty() && self.stuck.is_empty() && self.walls.is_empty()
+    }
+}
+
+fn pairs(cells: &[u32], state: FieldState) -> impl Iterator<Item = (usize, usize, FieldState)> + '_ {
+    cells.chunks(2).map(move |cell| (cell[0] as usize, cell[1] as usize, state))
+}
@@ -62,6 +62,7 @@ pub struct ColorizedPoint {
     pub x: usize,
     pub y: usize,
     pub state: AgentState,
+    #[serde(alias = "stickyNeighbor")]
     pub sticky_neighbor: Option<StickyNeighbor>,
     color: Color
 }
@@ -0,0 +1,343 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{DLAField, MAX_DENSE_CELLS};
+use crate::bytes::*;
+use crate::colorized_point::{AgentState, Color, ColorizedPoint, StickyNeighbor};
+use crate::rng::Rng;
+use crate::error::DlaError;
+
+// Compact encoding of a field for saves, a fraction of the size of a snapshot. Integers are
+// little endian, varints are LEB128
+//
+//   magic           4 bytes  "DLAC"
+//   version         u16      COMPRESSED_VERSION
+//   flags           u8       bit 0 sparse storage, bit 1 change tracking
+//   width, height   u32, u32
+//   canvas_id       u32 byte length followed by utf8
+//   rng state       u64
+//   agent count     u32
+//   runs            varint count, then a varint per run: length << 2 | state
+//                   state 0 empty, 1 free agent, 2 stuck agent, 3 wall (version 2)
+//   parents         4 bits per stuck agent, two to a byte, low bits first
+//                   0 root, 1-8 the neighbor in that direction, 15 listed in far parents
+//   far parents     varint count, then varint x, y of each
+//   palette         varint count, then r, g, b, a of each
+//   color indices   varint palette index per agent, only present with more than one color
+//
+// Runs cover the cells column by column, left to right, each column from the bottom row up,
+// which is also the order agents get in and the order parents and colors are listed in.
+// Whatever the runs leave out at the end is empty. Version 2 added walls, version 1 still loads
+pub const COMPRESSED_MAGIC: &[u8; 4] = b"DLAC";
+pub const COMPRESSED_VERSION: u16 = 2;
+
+const FLAG_SPARSE: u8 = 1;
+const FLAG_TRACK_CHANGES: u8 = 1 << 1;
+
+const RUN_EMPTY: u64 = 0;
+const RUN_FREE: u64 = 1;
+const RUN_STUCK: u64 = 2;
+const RUN_WALL: u64 = 3;
+
+const PARENT_ROOT: u8 = 0;
+const PARENT_FAR: u8 = 15;
+
+// (dx, dy) of parent codes 1 to 8
+const PARENT_DIRECTIONS: [(i64, i64); 8] = [
+    (-1, -1), (0, -1), (1, -1),
+    (-1, 0), (1, 0),
+    (-1, 1), (0, 1), (1, 1)
+];
+
+#[wasm_bindgen]
+impl DLAField {
+    pub fn to_compressed(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + self.canvas_id.len() + self.agents.len() * 2);
+
+        bytes.extend_from_slice(COMPRESSED_MAGIC);
+        push_u16(&mut bytes, COMPRESSED_VERSION);
+
+        let mut flags = 0;
+        if self.is_sparse() {
+            flags |= FLAG_SPARSE;
+        }
+        if self.track_changes {
+            flags |= FLAG_TRACK_CHANGES;
+        }
+        push_u8(&mut bytes, flags);
+
+        push_u32(&mut bytes, self.width as u32);
+        push_u32(&mut bytes, self.height as u32);
+        push_str(&mut bytes, &self.canvas_id);
+        push_u64(&mut bytes, self.rng.get_state());
+        push_u32(&mut bytes, self.agents.len() as u32);
+
+        let mut agents: Vec<&ColorizedPoint> = self.agents.iter().collect();
+        agents.sort_by_key(|agent| self.cell_order(agent.get_x(), agent.get_y()));
+
+        // (state, length) with neighboring runs of the same state merged
+        let mut runs: Vec<(u64, u64)> = vec![];
+        let mut push_run = |state: u64, length: u64| {
+            match runs.last_mut() {
+                Some(run) if run.0 == state => run.1 += length,
+                _ => runs.push((state, length))
+            }
+        };
+
+        let mut filled: Vec<(u64, u64)> = agents.iter()
+            .map(|agent| (self.cell_order(agent.get_x(), agent.get_y()), match agent.state {
+                AgentState::FREE => RUN_FREE,
+                AgentState::STUCK => RUN_STUCK
+            }))
+            .chain(self.walls().into_iter().map(|(x, y)| (self.cell_order(x, y), RUN_WALL)))
+            .collect();
+        filled.sort_unstable();
+
+        let mut next_cell = 0;
+        for (cell, state) in filled {
+            if cell > next_cell {
+                push_run(RUN_EMPTY, cell - next_cell);
+            }
+
+            push_run(state, 1);
+            next_cell = cell + 1;
+        }
+
+        push_varint(&mut bytes, runs.len() as u64);
+        for (state, length) in runs {
+            push_varint(&mut bytes, length << 2 | state);
+        }
+
+        let mut parent_codes = vec![];
+        let mut far_parents = vec![];
+        for agent in agents.iter().filter(|agent| agent.state == AgentState::STUCK) {
+            parent_codes.push(match agent.sticky_neighbor {
+                None => PARENT_ROOT,
+                Some(neighbor) => match parent_code(agent, neighbor) {
+                    Some(code) => code,
+                    None => {
+                        far_parents.push(neighbor);
+                        PARENT_FAR
+                    }
+                }
+            });
+        }
+
+        for pair in parent_codes.chunks(2) {
+            push_u8(&mut bytes, pair[0] | pair.get(1).map_or(0, |code| code << 4));
+        }
+
+        push_varint(&mut bytes, far_parents.len() as u64);
+        for neighbor in far_parents {
+            push_varint(&mut bytes, neighbor.x as u64);
+            push_varint(&mut bytes, neighbor.y as u64);
+        }
+
+        let mut palette: Vec<Color> = vec![];
+        let color_indices: Vec<usize> = agents.iter()
+            .map(|agent| {
+                let color = agent.get_color();
+                match palette.iter().position(|known| *known == color) {
+                    Some(ndx) => ndx,
+                    None => {
+                        palette.push(color);
+                        palette.len() - 1
+                    }
+                }
+            })
+            .collect();
+
+        push_varint(&mut bytes, palette.len() as u64);
+        for color in palette.iter() {
+            bytes.extend_from_slice(&[color.get_r(), color.get_g(), color.get_b(), color.get_a()]);
+        }
+
+        if palette.len() > 1 {
+            for ndx in color_indices {
+                push_varint(&mut bytes, ndx as u64);
+            }
+        }
+
+        bytes
+    }
+
+    // Agents come back in cell order rather than the order they had, everything else about the
+    // field is restored as it was
+    pub fn from_compressed(bytes: &[u8]) -> Result<DLAField, DlaError> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.read_bytes(4, "magic")? != COMPRESSED_MAGIC {
+            return Err(DlaError::InvalidData("magic: not a compressed DLAField".to_string()));
+        }
+
+        let version = reader.read_u16("version")?;
+        if version == 0 || version > COMPRESSED_VERSION {
+            return Err(DlaError::InvalidData(format!(
+                "version: compressed version {} is not supported, expected at most {}", version, COMPRESSED_VERSION)));
+        }
+
+        let flags = reader.read_u8("flags")?;
+        let width = reader.read_u32("width")? as usize;
+        let height = reader.read_u32("height")? as usize;
+        let canvas_id = reader.read_str("canvas_id")?;
+        let rng = Rng::new(reader.read_u64("rng")?);
+        let num_agents = reader.read_u32("agents")? as usize;
+        // runs of walls get expanded cell by cell, so the size has to be sane before reading them
+        DLAField::check_dimensions(width, height, flags & FLAG_SPARSE != 0)?;
+
+        let num_cells = (width as u64) * (height as u64);
+        let mut agents: Vec<ColorizedPoint> = Vec::with_capacity(num_agents.min(bytes.len()));
+        let mut walls = vec![];
+        let mut next_cell: u64 = 0;
+
+        let num_runs = reader.read_varint("runs")?;
+        for ndx in 0..num_runs {
+            let what = format!("runs[{}]", ndx);
+            let run = reader.read_varint(&what)?;
+            let (state, length) = (run & 3, run >> 2);
+
+            if length > num_cells - next_cell {
+                return Err(DlaError::InvalidData(format!("{}: runs past the last of the {} cells", what, num_cells)));
+            }
+
+            let cell_at = |cell: u64| ((cell / height as u64) as usize, height - 1 - (cell % height as u64) as usize);
+
+            let state = match state {
+                RUN_EMPTY => {
+                    next_cell += length;
+                    continue;
+                },
+                RUN_WALL if version < 2 =>
+                    return Err(DlaError::InvalidData(format!("{}: compressed version {} has no walls", what, version))),
+                RUN_WALL => {
+                    if length > (MAX_DENSE_CELLS - walls.len()) as u64 {
+                        return Err(DlaError::InvalidData(format!(
+                            "{}: more than {} walls", what, MAX_DENSE_CELLS)));
+                    }
+                    walls.extend((next_cell..next_cell + length).map(cell_at));
+                    next_cell += length;
+                    continue;
+                },
+                RUN_FREE => AgentState::FREE,
+                RUN_STUCK => AgentState::STUCK,
+                other => return Err(DlaError::InvalidData(format!("{}: unknown cell state {}", what, other)))
+            };
+
+            if length > (num_agents - agents.len()) as u64 {
+                return Err(DlaError::InvalidData(format!("{}: more agents than the {} in the header", what, num_agents)));
+            }
+
+            for cell in next_cell..next_cell + length {
+                let (x, y) = cell_at(cell);
+
+                let mut agent = ColorizedPoint::new(x, y, Color::new(255, 0, 0, 100), None);
+                agent.state = state;
+                agents.push(agent);
+            }
+            next_cell += length;
+        }
+
+        if agents.len() != num_agents {
+            return Err(DlaError::InvalidData(format!(
+                "runs: found {} agents, the header has {}", agents.len(), num_agents)));
+        }
+
+        let num_stuck = agents.iter().filter(|agent| agent.state == AgentState::STUCK).count();
+        let parent_bytes = reader.read_bytes(num_stuck.div_ceil(2), "parents")?;
+        let parent_codes: Vec<u8> = parent_bytes.iter()
+            .flat_map(|byte| vec![byte & 0xf, byte >> 4])
+            .take(num_stuck)
+            .collect();
+
+        let num_far_parents = reader.read_varint("far parents")?;
+        let mut far_parents = vec![];
+        for ndx in 0..num_far_parents.min(num_stuck as u64) {
+            let what = format!("far parents[{}]", ndx);
+            far_parents.push(StickyNeighbor::new(
+                reader.read_varint(&what)? as usize,
+                reader.read_varint(&what)? as usize
+            ));
+        }
+        let mut far_parents = far_parents.into_iter();
+
+        let stuck_agents = agents.iter_mut().filter(|agent| agent.state == AgentState::STUCK);
+        for (ndx, (agent, code)) in stuck_agents.zip(parent_codes).enumerate() {
+            agent.sticky_neighbor = match code {
+                PARENT_ROOT => None,
+                PARENT_FAR => Some(far_parents.next().ok_or_else(|| format!(
+                    "parents[{}]: refers to a far parent past the {} listed", ndx, num_far_parents))?),
+                code if (code as usize) <= PARENT_DIRECTIONS.len() => {
+                    let (dx, dy) = PARENT_DIRECTIONS[code as usize - 1];
+                    let x = agent.get_x() as i64 + dx;
+                    let y = agent.get_y() as i64 + dy;
+
+                    if x < 0 || y < 0 {
+                        return Err(DlaError::InvalidData(format!("parents[{}]: points outside of the field", ndx)));
+                    }
+                    Some(StickyNeighbor::new(x as usize, y as usize))
+                },
+                other => return Err(DlaError::InvalidData(format!("parents[{}]: unknown parent code {}", ndx, other)))
+            };
+        }
+
+        if far_parents.next().is_some() {
+            return Err(DlaError::InvalidData("far parents: more listed than referred to".to_string()));
+        }
+
+        let palette_len = reader.read_varint("palette")?;
+        let mut palette = vec![];
+        for ndx in 0..palette_len.min(num_agents as u64) {
+            let rgba = reader.read_bytes(4, &format!("palette[{}]", ndx))?;
+            palette.push(Color::new(rgba[0], rgba[1], rgba[2], rgba[3]));
+        }
+
+        if palette.is_empty() && num_agents > 0 {
+            return Err(DlaError::InvalidData("palette: empty".to_string()));
+        }
+
+        for (ndx, agent) in agents.iter_mut().enumerate() {
+            let color = if palette.len() == 1 {
+                palette[0]
+            } else {
+                let what = format!("color indices[{}]", ndx);
+                let color_ndx = reader.read_varint(&what)? as usize;
+
+                *palette.get(color_ndx).ok_or_else(|| format!(
+                    "{}: {} is past the {} colors of the palette", what, color_ndx, palette.len()))?
+            };
+
+            let state = agent.state;
+            *agent = ColorizedPoint::new(agent.get_x(), agent.get_y(), color, agent.sticky_neighbor);
+            agent.state = state;
+        }
+
+        if !reader.is_empty() {
+            return Err(DlaError::InvalidData("unexpected data after the color indices".to_string()));
+        }
+
+        let mut field = DLAField::from_agents(
+            canvas_id, width, height, flags & FLAG_SPARSE != 0, agents, rng)?;
+        field.add_walls(&walls)?;
+
+        if flags & FLAG_TRACK_CHANGES != 0 {
+            field.set_track_changes(true);
+        }
+
+        Ok(field)
+    }
+}
+
+impl DLAField {
+    // position of a cell in the order the runs cover them
+    fn cell_order(&self, x: usize, y: usize) -> u64 {
+        x as u64 * self.height as u64 + (self.height - 1 - y) as u64
+    }
+}
+
+fn parent_code(agent: &ColorizedPoint, neighbor: StickyNeighbor) -> Option<u8> {
+    let dx = neighbor.x as i64 - agent.get_x() as i64;
+    let dy = neighbor.y as i64 - agent.get_y() as i64;
+
+    PARENT_DIRECTIONS.iter()
+        .position(|direction| *direction == (dx, dy))
+        .map(|ndx| ndx as u8 + 1)
+}
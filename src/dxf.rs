@@ -0,0 +1,133 @@
+use std::fmt::Write;
+
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::error::DlaError;
+use crate::utils::format_decimal;
+
+// Settings for export_dxf. Drawings are in millimeters with every cell cell_size wide, the origin
+// at the bottom left corner of the field and y pointing up. By default the aggregate comes out
+// as LINE entities along the sticky neighbor links, set_outlines switches to closed polylines
+// around the stuck cells instead, which a laser cutter or CAD hatch can fill
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DxfOptions {
+    cell_size: f64,
+    outlines: bool
+}
+
+impl Default for DxfOptions {
+    fn default() -> DxfOptions {
+        DxfOptions {
+            cell_size: 1.0,
+            outlines: false
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl DxfOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> DxfOptions {
+        DxfOptions::default()
+    }
+
+    pub fn set_cell_size(&mut self, cell_size: f64) {
+        self.cell_size = cell_size;
+    }
+
+    pub fn set_outlines(&mut self, outlines: bool) {
+        self.outlines = outlines;
+    }
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    // An R12 DXF, the oldest flavor and the one every CAD and laser cutter program reads. Links
+    // go on the LINKS layer, outlines on OUTLINES. R12 has no header variable for units, the
+    // coordinates are millimeters by convention and most programs ask or assume as much on import
+    pub fn export_dxf(&self, options: &DxfOptions) -> Result<String, DlaError> {
+        if options.cell_size <= 0.0 || !options.cell_size.is_finite() {
+            return Err(DlaError::InvalidData(format!("cell_size: {} is not a positive length", options.cell_size)));
+        }
+
+        let mut dxf = String::new();
+        push_header(&mut dxf, &[("$ACADVER", 1, "AC1009")]);
+        push_group(&mut dxf, 0, "SECTION");
+        push_group(&mut dxf, 2, "ENTITIES");
+
+        if options.outlines {
+            let length = |value: usize| format_decimal(value as f64 * options.cell_size, 6);
+
+            for outline in self.stuck_outlines()? {
+                push_group(&mut dxf, 0, "POLYLINE");
+                push_group(&mut dxf, 8, "OUTLINES");
+                // vertices follow, closed
+                push_group(&mut dxf, 66, "1");
+                push_group(&mut dxf, 70, "1");
+                push_point(&mut dxf, 10, "0", "0");
+
+                for &(x, y) in outline.iter() {
+                    push_group(&mut dxf, 0, "VERTEX");
+                    push_group(&mut dxf, 8, "OUTLINES");
+                    push_point(&mut dxf, 10, &length(x), &length(y));
+                }
+
+                push_group(&mut dxf, 0, "SEQEND");
+                push_group(&mut dxf, 8, "OUTLINES");
+            }
+        } else {
+            let tree = self.aggregate_tree()?;
+            let center = |ndx: usize| {
+                let node = tree.nodes[ndx];
+                (
+                    format_decimal((node.x as f64 + 0.5) * options.cell_size, 6),
+                    format_decimal((self.height as f64 - 0.5 - node.y as f64) * options.cell_size, 6)
+                )
+            };
+
+            for (ndx, node) in tree.nodes.iter().enumerate() {
+                if let Some(parent) = node.parent {
+                    let (x1, y1) = center(parent);
+                    let (x2, y2) = center(ndx);
+
+                    push_group(&mut dxf, 0, "LINE");
+                    push_group(&mut dxf, 8, "LINKS");
+                    push_point(&mut dxf, 10, &x1, &y1);
+                    push_point(&mut dxf, 11, &x2, &y2);
+                }
+            }
+        }
+
+        push_group(&mut dxf, 0, "ENDSEC");
+        push_group(&mut dxf, 0, "EOF");
+
+        Ok(dxf)
+    }
+}
+
+fn push_header(dxf: &mut String, variables: &[(&str, u32, &str)]) {
+    push_group(dxf, 0, "SECTION");
+    push_group(dxf, 2, "HEADER");
+
+    for &(name, code, value) in variables.iter() {
+        push_group(dxf, 9, name);
+        push_group(dxf, code, value);
+    }
+
+    push_group(dxf, 0, "ENDSEC");
+}
+
+// a point is its x code followed by y and z, 10 more each time
+fn push_point(dxf: &mut String, code: u32, x: &str, y: &str) {
+    push_group(dxf, code, x);
+    push_group(dxf, code + 10, y);
+    push_group(dxf, code + 20, "0");
+}
+
+// DXF is a list of group code and value pairs, each on its own line
+fn push_group(dxf: &mut String, code: u32, value: &str) {
+    // writing to a String can't fail
+    let _ = write!(dxf, "{:>3}\n{}\n", code, value);
+}
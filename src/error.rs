@@ -0,0 +1,75 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+// Everything the public API can fail with. On the JS side these arrive as thrown Errors carrying
+// the Display message, so a bad call fails that call instead of taking down the wasm instance
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DlaError {
+    NoWindow,
+    NoDocument,
+    CanvasNotFound(String),
+    NotACanvas(String),
+    NoContext(String),
+    // a canvas call was rejected by the browser, with its message
+    Canvas(String),
+    AgentIndexOutOfRange { ndx: usize, num_agents: usize },
+    TooManyAgents { num_agents: usize, capacity: usize },
+    MissingNeighbor { x: usize, y: usize },
+    NeighborCycle { x: usize, y: usize },
+    // sparse fields keep their cells in tiles, there is no single array to hand out
+    SparseField,
+    TickOutOfRange { tick: usize, oldest: usize, newest: usize },
+    // a replay regenerated a different field than the one it recorded, digests in hex
+    ReplayMismatch { tick: usize, expected: String, found: String },
+    // an autosave snapshot could not be written or read back
+    Autosave(String),
+    // snapshots, JSON and imports that don't describe a valid field
+    InvalidData(String),
+    // every violation DLAField::validate found
+    Inconsistent(Vec<String>)
+}
+
+impl fmt::Display for DlaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DlaError::NoWindow => write!(f, "no global window, not running in a browser"),
+            DlaError::NoDocument => write!(f, "the window has no document"),
+            DlaError::CanvasNotFound(canvas_id) => write!(f, "no element with id '{}'", canvas_id),
+            DlaError::NotACanvas(canvas_id) => write!(f, "element '{}' is not a canvas", canvas_id),
+            DlaError::NoContext(canvas_id) => write!(f, "canvas '{}' has no 2d context", canvas_id),
+            DlaError::Canvas(message) => write!(f, "canvas error: {}", message),
+            DlaError::AgentIndexOutOfRange { ndx, num_agents } =>
+                write!(f, "agent index {} is out of range, the field has {} agents", ndx, num_agents),
+            DlaError::TooManyAgents { num_agents, capacity } =>
+                write!(f, "{} agents don't fit on a field with {} cells", num_agents, capacity),
+            DlaError::MissingNeighbor { x, y } =>
+                write!(f, "sticky neighbor at ({}, {}) has no agent", x, y),
+            DlaError::NeighborCycle { x, y } =>
+                write!(f, "sticky neighbors starting at ({}, {}) loop back on themselves", x, y),
+            DlaError::SparseField => write!(f, "sparse fields have no contiguous position hash"),
+            DlaError::TickOutOfRange { tick, oldest, newest } =>
+                write!(f, "tick {} is not in the history, it holds ticks {} to {}", tick, oldest, newest),
+            DlaError::ReplayMismatch { tick, expected, found } =>
+                write!(f, "replay diverged at tick {}, expected digest {} but found {}", tick, expected, found),
+            DlaError::Autosave(message) => write!(f, "autosave failed: {}", message),
+            DlaError::InvalidData(message) => write!(f, "{}", message),
+            DlaError::Inconsistent(violations) =>
+                write!(f, "field is inconsistent: {}", violations.join("; "))
+        }
+    }
+}
+
+impl std::error::Error for DlaError {}
+
+impl From<String> for DlaError {
+    fn from(message: String) -> DlaError {
+        DlaError::InvalidData(message)
+    }
+}
+
+impl From<DlaError> for JsValue {
+    fn from(error: DlaError) -> JsValue {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
@@ -0,0 +1,164 @@
+use serde_json::{Map, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::colorized_point::{AgentState, Color, ColorizedPoint, StickyNeighbor};
+use crate::rng::Rng;
+use crate::error::DlaError;
+
+impl DLAField {
+    // Turns a plain list of agents, the shape the web page saves, into a field. Keys are accepted
+    // in snake_case as well as camelCase (sticky_neighbor or stickyNeighbor), state may be the name
+    // of an AgentState or its numeric value and color falls back to the default agent color. Any
+    // problem is reported with the path of the offending value, e.g. "agents[3].sticky_neighbor.x"
+    pub fn from_agents_value(
+        canvas_id: String,
+        width: usize,
+        height: usize,
+        agents: &Value
+    ) -> Result<DLAField, DlaError> {
+        let agents = agents.as_array()
+            .ok_or_else(|| format!("agents: expected an array, found {}", describe(agents)))?;
+
+        let agents = agents.iter()
+            .enumerate()
+            .map(|(ndx, agent)| read_agent(agent, &format!("agents[{}]", ndx)))
+            .collect::<Result<Vec<ColorizedPoint>, String>>()?;
+
+        let field = DLAField::from_agents(canvas_id, width, height, false, agents, Rng::from_entropy())?;
+
+        for (ndx, agent) in field.agents.iter().enumerate() {
+            if let Some(neighbor) = agent.sticky_neighbor {
+                if neighbor.x >= width || neighbor.y >= height {
+                    return Err(DlaError::InvalidData(format!(
+                        "agents[{}].sticky_neighbor: ({}, {}) is outside of the {}x{} field",
+                        ndx, neighbor.x, neighbor.y, width, height)));
+                }
+            }
+        }
+
+        Ok(field)
+    }
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    // same as from_agents_value, for agents handed over as a JSON string
+    pub fn from_agents_json(canvas_id: String, width: usize, height: usize, json: &str) -> Result<DLAField, DlaError> {
+        let agents: Value = serde_json::from_str(json).map_err(|err| format!("agents: {}", err))?;
+
+        DLAField::from_agents_value(canvas_id, width, height, &agents)
+    }
+}
+
+#[wasm_bindgen]
+pub fn build_field_from_js_state(canvas_id: String, width: usize, height: usize, agents: JsValue) -> Result<DLAField, DlaError> {
+    let json: String = js_sys::JSON::stringify(&agents)
+        .map_err(|_| "agents: could not be converted to JSON".to_string())?
+        .into();
+
+    DLAField::from_agents_json(canvas_id, width, height, &json)
+}
+
+fn read_agent(value: &Value, path: &str) -> Result<ColorizedPoint, String> {
+    let agent = as_object(value, path)?;
+
+    let x = read_usize(agent, path, &["x"])?;
+    let y = read_usize(agent, path, &["y"])?;
+
+    let color = match field(agent, &["color"]) {
+        None | Some(Value::Null) => Color::new(255, 0, 0, 100),
+        Some(color) => read_color(color, &format!("{}.color", path))?
+    };
+
+    let sticky_neighbor = match field(agent, &["sticky_neighbor", "stickyNeighbor"]) {
+        None | Some(Value::Null) => None,
+        Some(neighbor) => {
+            let neighbor_path = format!("{}.sticky_neighbor", path);
+            let neighbor = as_object(neighbor, &neighbor_path)?;
+
+            Some(StickyNeighbor::new(
+                read_usize(neighbor, &neighbor_path, &["x"])?,
+                read_usize(neighbor, &neighbor_path, &["y"])?
+            ))
+        }
+    };
+
+    let state = match field(agent, &["state"]) {
+        None | Some(Value::Null) => AgentState::FREE,
+        Some(state) => read_state(state, &format!("{}.state", path))?
+    };
+
+    if let (AgentState::FREE, Some(_)) = (state, sticky_neighbor) {
+        return Err(format!("{}.sticky_neighbor: a free agent can't have a sticky neighbor", path));
+    }
+
+    let mut agent = ColorizedPoint::new(x, y, color, sticky_neighbor);
+    agent.state = state;
+
+    Ok(agent)
+}
+
+fn read_state(value: &Value, path: &str) -> Result<AgentState, String> {
+    match value {
+        Value::String(name) if name == "FREE" => Ok(AgentState::FREE),
+        Value::String(name) if name == "STUCK" => Ok(AgentState::STUCK),
+        // wasm-bindgen hands enums to JS as their discriminant
+        Value::Number(number) if number.as_u64() == Some(AgentState::FREE as u64) => Ok(AgentState::FREE),
+        Value::Number(number) if number.as_u64() == Some(AgentState::STUCK as u64) => Ok(AgentState::STUCK),
+        _ => Err(format!("{}: expected \"FREE\", \"STUCK\", 0 or 1, found {}", path, describe(value)))
+    }
+}
+
+fn read_color(value: &Value, path: &str) -> Result<Color, String> {
+    let color = as_object(value, path)?;
+
+    Ok(Color::new(
+        read_u8(color, path, "r")?,
+        read_u8(color, path, "g")?,
+        read_u8(color, path, "b")?,
+        read_u8(color, path, "a")?
+    ))
+}
+
+fn read_u8(object: &Map<String, Value>, path: &str, key: &str) -> Result<u8, String> {
+    let value = read_usize(object, path, &[key])?;
+
+    if value > u8::MAX as usize {
+        return Err(format!("{}.{}: expected a value from 0 to 255, found {}", path, key, value));
+    }
+
+    Ok(value as u8)
+}
+
+// the first of the given spellings of a key that is present
+fn field<'a>(object: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().filter_map(|key| object.get(*key)).next()
+}
+
+fn read_usize(object: &Map<String, Value>, path: &str, keys: &[&str]) -> Result<usize, String> {
+    let key_path = format!("{}.{}", path, keys[0]);
+
+    match field(object, keys) {
+        None => Err(format!("{}: missing", key_path)),
+        Some(value) => value.as_u64()
+            .map(|value| value as usize)
+            .ok_or_else(|| format!("{}: expected a non-negative integer, found {}", key_path, describe(value)))
+    }
+}
+
+fn as_object<'a>(value: &'a Value, path: &str) -> Result<&'a Map<String, Value>, String> {
+    value.as_object()
+        .ok_or_else(|| format!("{}: expected an object, found {}", path, describe(value)))
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(_) => "a boolean".to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::String(string) => format!("\"{}\"", string),
+        Value::Array(_) => "an array".to_string(),
+        Value::Object(_) => "an object".to_string()
+    }
+}
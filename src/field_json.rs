@@ -0,0 +1,181 @@
+use std::convert::TryFrom;
+
+use serde::{Serialize, Serializer};
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::colorized_point::ColorizedPoint;
+use crate::field_position::FieldState;
+use crate::rng::Rng;
+use crate::error::DlaError;
+
+// JSON form of a field, this is what DLAField serializes to and from through serde
+//
+// {
+//   "version": 1,
+//   "width": 100,
+//   "height": 100,
+//   "canvas_id": "dla-display-1",
+//   "sparse": false,               // storage backend, see DLAField::new_sparse
+//   "track_changes": false,        // whether next_state records change sets
+//   "rng_state": "1234567890",     // u64 as a string, JS numbers can't hold all of it
+//   "agents": [
+//     {
+//       "x": 4, "y": 99,
+//       "state": "STUCK",           // or "FREE"
+//       "sticky_neighbor": { "x": 5, "y": 99 },   // null for roots and free agents
+//       "color": { "r": 255, "g": 0, "b": 0, "a": 100 }
+//     }
+//   ],
+//   "walls": [[0, 98], [1, 98]],   // [x, y] of each wall, left out when there are none
+//   "cells": [                     // one string per row, top row first, dense fields only
+//     "....o.....",                // '.' empty, 'o' free agent, '#' stuck agent, 'X' wall
+//     "XX..#....."
+//   ]
+// }
+//
+// Agents are kept in the field's own order. cells is derived from the agents and walls, when
+// present on import it has to agree with them. It is left out for sparse fields, which are meant
+// to be far larger than anything worth spelling out cell by cell, so their cell states only come
+// from agents and walls
+pub const FIELD_JSON_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FieldJson {
+    pub version: u32,
+    pub width: usize,
+    pub height: usize,
+    pub canvas_id: String,
+    #[serde(default)]
+    pub sparse: bool,
+    #[serde(default)]
+    pub track_changes: bool,
+    pub rng_state: String,
+    pub agents: Vec<ColorizedPoint>,
+    #[serde(default)]
+    pub walls: Vec<[usize; 2]>,
+    #[serde(default)]
+    pub cells: Option<Vec<String>>
+}
+
+const CELL_EMPTY: char = '.';
+const CELL_OCCUPIED: char = 'o';
+const CELL_STUCK: char = '#';
+const CELL_WALL: char = 'X';
+
+// The same layout as FieldJson for writing, borrowing from the field instead of copying it
+#[derive(Serialize)]
+struct FieldJsonView<'a> {
+    version: u32,
+    width: usize,
+    height: usize,
+    canvas_id: &'a str,
+    sparse: bool,
+    track_changes: bool,
+    rng_state: String,
+    agents: &'a [ColorizedPoint],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    walls: Vec<[usize; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cells: Option<Vec<String>>
+}
+
+impl Serialize for DLAField {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let cells = if self.is_sparse() {
+            None
+        } else {
+            Some((0..self.height).map(|y| {
+                (0..self.width).map(|x| cell_char(self.position_hash.get(x, y).state)).collect()
+            }).collect())
+        };
+
+        FieldJsonView {
+            version: FIELD_JSON_VERSION,
+            width: self.width,
+            height: self.height,
+            canvas_id: &self.canvas_id,
+            sparse: self.is_sparse(),
+            track_changes: self.track_changes,
+            rng_state: self.rng.get_state().to_string(),
+            agents: &self.agents,
+            walls: self.walls().into_iter().map(|(x, y)| [x, y]).collect(),
+            cells
+        }.serialize(serializer)
+    }
+}
+
+impl TryFrom<FieldJson> for DLAField {
+    type Error = DlaError;
+
+    fn try_from(json: FieldJson) -> Result<DLAField, DlaError> {
+        if json.version != FIELD_JSON_VERSION {
+            return Err(DlaError::InvalidData(format!(
+                "version: field json version {} is not supported, expected {}",
+                json.version, FIELD_JSON_VERSION)));
+        }
+
+        let rng_state: u64 = json.rng_state.parse()
+            .map_err(|_| format!("rng_state: '{}' is not an unsigned 64 bit integer", json.rng_state))?;
+
+        let mut field = DLAField::from_agents(
+            json.canvas_id, json.width, json.height, json.sparse, json.agents, Rng::new(rng_state))?;
+        let walls: Vec<(usize, usize)> = json.walls.iter().map(|wall| (wall[0], wall[1])).collect();
+        field.add_walls(&walls)?;
+
+        if let Some(cells) = json.cells {
+            check_cells(&field, &cells)?;
+        }
+
+        if json.track_changes {
+            field.set_track_changes(true);
+        }
+
+        Ok(field)
+    }
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    pub fn to_json(&self) -> Result<String, DlaError> {
+        serde_json::to_string(self).map_err(|err| DlaError::InvalidData(err.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<DLAField, DlaError> {
+        serde_json::from_str(json).map_err(|err| DlaError::InvalidData(err.to_string()))
+    }
+}
+
+fn cell_char(state: FieldState) -> char {
+    match state {
+        FieldState::EMPTY => CELL_EMPTY,
+        FieldState::OCCUPIED => CELL_OCCUPIED,
+        FieldState::STUCK => CELL_STUCK,
+        FieldState::WALL => CELL_WALL
+    }
+}
+
+fn check_cells(field: &DLAField, cells: &[String]) -> Result<(), String> {
+    if cells.len() != field.height {
+        return Err(format!("cells: expected {} rows, found {}", field.height, cells.len()));
+    }
+
+    for (y, row) in cells.iter().enumerate() {
+        let row: Vec<char> = row.chars().collect();
+
+        if row.len() != field.width {
+            return Err(format!("cells[{}]: expected {} columns, found {}", y, field.width, row.len()));
+        }
+
+        for (x, cell) in row.into_iter().enumerate() {
+            let expected = cell_char(field.position_hash.get(x, y).state);
+
+            if cell != expected {
+                return Err(format!(
+                    "cells[{}][{}]: '{}' does not match the agents, expected '{}'", y, x, cell, expected));
+            }
+        }
+    }
+
+    Ok(())
+}
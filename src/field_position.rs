@@ -5,7 +5,9 @@ use crate::colorized_point::ColorizedPoint;
 pub enum FieldState {
     EMPTY,
     OCCUPIED,
-    STUCK
+    STUCK,
+    // an obstacle, never holds an agent
+    WALL
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -0,0 +1,164 @@
+use std::fmt::Write;
+
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::error::DlaError;
+use crate::utils::format_decimal;
+
+// Paper and machine settings for export_gcode, lengths in millimeters and rates in mm/min. The
+// field is scaled to fit inside the margins and centered, with y flipped since plotters have the
+// origin at the bottom left
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GcodeOptions {
+    paper_width: f64,
+    paper_height: f64,
+    margin: f64,
+    feed_rate: f64,
+    travel_rate: f64,
+    pen_up: String,
+    pen_down: String
+}
+
+impl Default for GcodeOptions {
+    // A4 portrait and a pen on the Z axis
+    fn default() -> GcodeOptions {
+        GcodeOptions {
+            paper_width: 210.0,
+            paper_height: 297.0,
+            margin: 10.0,
+            feed_rate: 1500.0,
+            travel_rate: 3000.0,
+            pen_up: "G0 Z5".to_string(),
+            pen_down: "G0 Z0".to_string()
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl GcodeOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GcodeOptions {
+        GcodeOptions::default()
+    }
+
+    pub fn set_paper_size(&mut self, width: f64, height: f64) {
+        self.paper_width = width;
+        self.paper_height = height;
+    }
+
+    pub fn set_margin(&mut self, margin: f64) {
+        self.margin = margin;
+    }
+
+    pub fn set_feed_rate(&mut self, feed_rate: f64) {
+        self.feed_rate = feed_rate;
+    }
+
+    pub fn set_travel_rate(&mut self, travel_rate: f64) {
+        self.travel_rate = travel_rate;
+    }
+
+    // the lines that lift and lower the pen, e.g. "M3 S0" and "M3 S90" for a servo
+    pub fn set_pen_commands(&mut self, pen_up: String, pen_down: String) {
+        self.pen_up = pen_up;
+        self.pen_down = pen_down;
+    }
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    // The stuck agents as pen plotter strokes along their sticky neighbor links. Every stroke is
+    // one continuous line, and the next stroke is always whichever end of the remaining ones is
+    // closest to the pen, so little time goes to moving with the pen up
+    pub fn export_gcode(&self, options: &GcodeOptions) -> Result<String, DlaError> {
+        let drawable_width = options.paper_width - 2.0 * options.margin;
+        let drawable_height = options.paper_height - 2.0 * options.margin;
+        if drawable_width <= 0.0 || drawable_height <= 0.0 || self.width == 0 || self.height == 0 {
+            return Err(DlaError::InvalidData(format!(
+                "paper: nothing fits on {}x{}mm with a {}mm margin",
+                options.paper_width, options.paper_height, options.margin)));
+        }
+
+        let tree = self.aggregate_tree()?;
+
+        let cell_size = (drawable_width / self.width as f64).min(drawable_height / self.height as f64);
+        let offset_x = options.margin + (drawable_width - cell_size * self.width as f64) / 2.0;
+        let offset_y = options.margin + (drawable_height - cell_size * self.height as f64) / 2.0;
+        let position = |ndx: usize| {
+            let node = tree.nodes[ndx];
+            (
+                offset_x + (node.x as f64 + 0.5) * cell_size,
+                offset_y + (self.height as f64 - 0.5 - node.y as f64) * cell_size
+            )
+        };
+
+        let strokes = order_strokes(tree.strokes(), &position);
+
+        let mut gcode = String::new();
+        // writing to a String can't fail
+        let _ = writeln!(gcode, "; wasm-rust-dla, {} strokes on {}x{}mm", strokes.len(),
+            format_decimal(options.paper_width, 3), format_decimal(options.paper_height, 3));
+        let _ = writeln!(gcode, "G21 ; millimeters");
+        let _ = writeln!(gcode, "G90 ; absolute coordinates");
+        let _ = writeln!(gcode, "{}", options.pen_up);
+
+        for stroke in strokes.iter() {
+            let (x, y) = position(stroke[0]);
+            let _ = writeln!(gcode, "G0 X{} Y{} F{}", format_decimal(x, 3), format_decimal(y, 3), format_decimal(options.travel_rate, 3));
+            let _ = writeln!(gcode, "{}", options.pen_down);
+
+            for &ndx in stroke[1..].iter() {
+                let (x, y) = position(ndx);
+                let _ = writeln!(gcode, "G1 X{} Y{} F{}", format_decimal(x, 3), format_decimal(y, 3), format_decimal(options.feed_rate, 3));
+            }
+
+            let _ = writeln!(gcode, "{}", options.pen_up);
+        }
+
+        let _ = writeln!(gcode, "G0 X0 Y0 F{}", format_decimal(options.travel_rate, 3));
+
+        Ok(gcode)
+    }
+}
+
+// Greedy nearest neighbor ordering starting from the origin, strokes get reversed when their far
+// end is the closer one
+fn order_strokes<F>(mut strokes: Vec<Vec<usize>>, position: &F) -> Vec<Vec<usize>>
+    where F: Fn(usize) -> (f64, f64)
+{
+    let distance = |from: (f64, f64), ndx: usize| {
+        let to = position(ndx);
+        (from.0 - to.0).powi(2) + (from.1 - to.1).powi(2)
+    };
+
+    let mut ordered = Vec::with_capacity(strokes.len());
+    let mut pen = (0.0, 0.0);
+
+    while !strokes.is_empty() {
+        let mut best = (f64::INFINITY, 0, false);
+
+        for (ndx, stroke) in strokes.iter().enumerate() {
+            let to_start = distance(pen, stroke[0]);
+            let to_end = distance(pen, stroke[stroke.len() - 1]);
+
+            if to_start < best.0 {
+                best = (to_start, ndx, false);
+            }
+            if to_end < best.0 {
+                best = (to_end, ndx, true);
+            }
+        }
+
+        let mut stroke = strokes.swap_remove(best.1);
+        if best.2 {
+            stroke.reverse();
+        }
+
+        pen = position(stroke[stroke.len() - 1]);
+        ordered.push(stroke);
+    }
+
+    ordered
+}
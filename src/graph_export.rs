@@ -0,0 +1,79 @@
+use std::fmt::Write;
+
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::colorized_point::Color;
+use crate::error::DlaError;
+
+// The stuck agents as a directed forest for graph tools like networkx, Gephi and Graphviz. Nodes
+// are n0, n1, ... in field order and carry the cell coordinates, the hops to their root, the size
+// of their subtree and the agent color. Edges point from the sticky neighbor to the agent that
+// stuck to it, so they run from the roots outwards
+#[wasm_bindgen]
+impl DLAField {
+    pub fn export_graphml(&self) -> Result<String, DlaError> {
+        let tree = self.aggregate_tree()?;
+        let mut graphml = String::new();
+
+        // writing to a String can't fail
+        let _ = writeln!(graphml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(graphml, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#);
+        for (key, key_type) in [("x", "int"), ("y", "int"), ("depth", "int"), ("subtree_size", "int"),
+            ("color", "string"), ("alpha", "int")].iter() {
+            let _ = writeln!(graphml, r#"  <key id="{}" for="node" attr.name="{}" attr.type="{}"/>"#, key, key, key_type);
+        }
+        let _ = writeln!(graphml, r#"  <graph id="{}" edgedefault="directed">"#, xml_escape(&self.canvas_id));
+
+        for (ndx, node) in tree.nodes.iter().enumerate() {
+            let _ = writeln!(graphml, concat!(
+                r#"    <node id="n{}"><data key="x">{}</data><data key="y">{}</data><data key="depth">{}</data>"#,
+                r#"<data key="subtree_size">{}</data><data key="color">{}</data><data key="alpha">{}</data></node>"#),
+                ndx, node.x, node.y, node.depth, node.subtree_size, hex_color(node.color), node.color.get_a());
+        }
+
+        for (ndx, node) in tree.nodes.iter().enumerate() {
+            if let Some(parent) = node.parent {
+                let _ = writeln!(graphml, r#"    <edge source="n{}" target="n{}"/>"#, parent, ndx);
+            }
+        }
+
+        let _ = writeln!(graphml, "  </graph>");
+        let _ = writeln!(graphml, "</graphml>");
+
+        Ok(graphml)
+    }
+
+    // pos pins every node to its cell for neato -n, flipped since Graphviz has y pointing up
+    pub fn export_dot(&self) -> Result<String, DlaError> {
+        let tree = self.aggregate_tree()?;
+        let mut dot = String::new();
+
+        let _ = writeln!(dot, "digraph dla {{");
+        let _ = writeln!(dot, "  node [shape=point];");
+
+        for (ndx, node) in tree.nodes.iter().enumerate() {
+            let _ = writeln!(dot, r#"  n{} [x={}, y={}, depth={}, subtree_size={}, color="{}", pos="{},{}!"];"#,
+                ndx, node.x, node.y, node.depth, node.subtree_size, hex_color(node.color),
+                node.x, self.height - 1 - node.y);
+        }
+
+        for (ndx, node) in tree.nodes.iter().enumerate() {
+            if let Some(parent) = node.parent {
+                let _ = writeln!(dot, "  n{} -> n{};", parent, ndx);
+            }
+        }
+
+        let _ = writeln!(dot, "}}");
+
+        Ok(dot)
+    }
+}
+
+fn hex_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.get_r(), color.get_g(), color.get_b())
+}
+
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::colorized_point::{AgentState, ColorizedPoint, StickyNeighbor};
+use crate::field_position::{FieldPosition, FieldState};
+use crate::rng::Rng;
+use crate::error::DlaError;
+
+// What one call to next_state did. next_state pushes the agents in the scan order of their old
+// cells, so agents are referred to by their position in that order
+#[derive(Clone, Debug)]
+struct TickDelta {
+    // scan index, new x, new y
+    moves: Vec<(u32, u32, u32)>,
+    // scan index, the neighbor it stuck to
+    stuck: Vec<(u32, Option<StickyNeighbor>)>,
+    rng_state: u64
+}
+
+impl TickDelta {
+    fn memory_used(&self) -> usize {
+        size_of::<TickDelta>()
+            + self.moves.len() * size_of::<(u32, u32, u32)>()
+            + self.stuck.len() * size_of::<(u32, Option<StickyNeighbor>)>()
+    }
+}
+
+// Records every tick of a field as a delta so it can be rewound to any earlier tick and played
+// forward again. Every keyframe_interval ticks a full snapshot is kept as well, rewinding starts
+// from the closest one before the tick. Once the history takes up more than memory_cap bytes the
+// oldest keyframe and the deltas up to the next one are dropped, there is always at least one
+// keyframe left. A history follows the one field it was created from, stepping or rewinding any
+// other field gives meaningless results
+#[wasm_bindgen]
+pub struct History {
+    memory_cap: usize,
+    keyframe_interval: usize,
+    // (tick, DLAField::to_bytes at that tick), oldest first
+    keyframes: VecDeque<(usize, Vec<u8>)>,
+    // deltas[0] goes from the first keyframe's tick to the one after it
+    deltas: VecDeque<TickDelta>,
+    memory_used: usize,
+    // tick the field is at, below newest_tick after a rewind
+    tick: usize
+}
+
+#[wasm_bindgen]
+impl History {
+    #[wasm_bindgen(constructor)]
+    pub fn new(field: &DLAField, memory_cap: usize, keyframe_interval: usize) -> History {
+        let mut history = History {
+            memory_cap,
+            keyframe_interval: keyframe_interval.max(1),
+            keyframes: VecDeque::new(),
+            deltas: VecDeque::new(),
+            memory_used: 0,
+            tick: 0
+        };
+        history.push_keyframe(0, field);
+
+        history
+    }
+
+    pub fn get_tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn get_oldest_tick(&self) -> usize {
+        self.keyframes[0].0
+    }
+
+    pub fn get_newest_tick(&self) -> usize {
+        self.get_oldest_tick() + self.deltas.len()
+    }
+
+    pub fn get_memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    // Moves the field one tick forward. Ticks that were already recorded, after a rewind, are
+    // played back from their deltas, past the newest tick next_state runs and gets recorded
+    pub fn step(&mut self, field: &mut DLAField) -> bool {
+        if self.tick < self.get_newest_tick() {
+            let has_next_state = field.occupancy.occupied_count() > field.occupancy.stuck_count();
+            field.apply_delta(&self.deltas[self.tick - self.get_oldest_tick()]);
+            self.tick += 1;
+
+            return has_next_state;
+        }
+
+        let before: Vec<ColorizedPoint> = field.scan_order().into_iter()
+            .filter_map(|(x, y)| field.get_agent_at_coordinate(x, y))
+            .collect();
+
+        let has_next_state = field.next_state();
+
+        let mut delta = TickDelta { moves: vec![], stuck: vec![], rng_state: field.rng.get_state() };
+        for (ndx, (old, new)) in before.iter().zip(field.agents.iter()).enumerate() {
+            if old.get_x() != new.get_x() || old.get_y() != new.get_y() {
+                delta.moves.push((ndx as u32, new.get_x() as u32, new.get_y() as u32));
+            }
+
+            if let (AgentState::FREE, AgentState::STUCK) = (old.state, new.state) {
+                delta.stuck.push((ndx as u32, new.sticky_neighbor));
+            }
+        }
+
+        self.memory_used += delta.memory_used();
+        self.deltas.push_back(delta);
+        self.tick += 1;
+
+        if self.tick.is_multiple_of(self.keyframe_interval) {
+            self.push_keyframe(self.tick, field);
+        }
+
+        self.enforce_memory_cap();
+
+        has_next_state
+    }
+
+    // Puts the field back the way it was at the given tick, stepping from there replays the
+    // recorded ticks before simulating new ones
+    pub fn rewind(&mut self, field: &mut DLAField, tick: usize) -> Result<(), DlaError> {
+        let track_changes = field.track_changes;
+
+        *field = self.field_at(tick)?;
+        field.set_track_changes(track_changes);
+        self.tick = tick;
+
+        Ok(())
+    }
+
+    // a copy of the field at the given tick, leaves the followed field alone
+    pub fn field_at(&self, tick: usize) -> Result<DLAField, DlaError> {
+        if tick < self.get_oldest_tick() || tick > self.get_newest_tick() {
+            return Err(DlaError::TickOutOfRange {
+                tick,
+                oldest: self.get_oldest_tick(),
+                newest: self.get_newest_tick()
+            });
+        }
+
+        let (keyframe_tick, snapshot) = self.keyframes.iter()
+            .rev()
+            .find(|(keyframe_tick, _)| *keyframe_tick <= tick)
+            .expect("the oldest keyframe is at the oldest tick");
+
+        let mut field = DLAField::from_bytes(snapshot)?;
+        for ndx in *keyframe_tick..tick {
+            field.apply_delta(&self.deltas[ndx - self.get_oldest_tick()]);
+        }
+
+        Ok(field)
+    }
+}
+
+impl History {
+    fn push_keyframe(&mut self, tick: usize, field: &DLAField) {
+        let snapshot = field.to_bytes();
+
+        self.memory_used += snapshot.len();
+        self.keyframes.push_back((tick, snapshot));
+    }
+
+    fn enforce_memory_cap(&mut self) {
+        while self.memory_used > self.memory_cap && self.keyframes.len() > 1 {
+            let (oldest, snapshot) = self.keyframes.pop_front().unwrap();
+            self.memory_used -= snapshot.len();
+
+            for _ in oldest..self.keyframes[0].0 {
+                let delta = self.deltas.pop_front().unwrap();
+                self.memory_used -= delta.memory_used();
+            }
+        }
+    }
+}
+
+impl DLAField {
+    // redoes a recorded tick the same way next_state did it, change tracking included
+    fn apply_delta(&mut self, delta: &TickDelta) {
+        let mut agents: Vec<ColorizedPoint> = self.scan_order().into_iter()
+            .filter_map(|(x, y)| self.get_agent_at_coordinate(x, y))
+            .collect();
+
+        for &(ndx, x, y) in delta.moves.iter() {
+            let ndx = ndx as usize;
+            let mut agent = agents[ndx];
+
+            self.move_position(&mut agent, x as usize, y as usize, ndx);
+            agents[ndx] = agent;
+        }
+
+        for &(ndx, sticky_neighbor) in delta.stuck.iter() {
+            let agent = &mut agents[ndx as usize];
+            agent.state = AgentState::STUCK;
+            agent.sticky_neighbor = sticky_neighbor;
+
+            let (x, y) = (agent.get_x(), agent.get_y());
+            self.position_hash.set(x, y, FieldPosition::new(FieldState::STUCK, Some(*agent)));
+            self.occupancy.set_stuck(x, y, true);
+
+            if self.track_changes {
+                self.changes.record_stuck(x, y, sticky_neighbor);
+            }
+        }
+
+        let mut agent_position_lookup = self.agent_position_lookup.empty_like();
+        for (ndx, agent) in agents.iter().enumerate() {
+            agent_position_lookup.set(agent.get_x(), agent.get_y(), Some(ndx));
+        }
+
+        self.agents = agents;
+        self.agent_position_lookup = agent_position_lookup;
+        self.rng = Rng::new(delta.rng_state);
+    }
+}
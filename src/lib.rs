@@ -7,19 +7,71 @@ extern crate web_sys;
 mod utils;
 mod colorized_point;
 mod field_position;
+mod canvas_renderer;
+mod palette;
+mod png;
+mod tree;
+mod svg;
+mod graph_export;
+mod newick;
+mod gcode;
+mod outline;
+mod dxf;
+mod stl;
+mod arrival;
+mod npy;
+mod walls;
+mod bitmap;
+mod bitmap_import;
+mod agent_table;
+mod change_set;
+mod cell_grid;
+mod rng;
+mod occupancy;
+mod bytes;
+mod snapshot;
+mod compressed;
+mod field_json;
+mod field_import;
+mod error;
+mod validate;
+mod history;
+mod replay;
+mod autosave;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod parallel;
 
 use wasm_bindgen::prelude::*;
-use js_sys::{Math, Array, ArrayIter};
-use web_sys::*;
 use wasm_bindgen::convert::{FromWasmAbi, WasmAbi};
-use std::f64;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
 
 use crate::colorized_point::*;
 
 use crate::field_position::FieldPosition;
 use crate::field_position::FieldState;
+use crate::canvas_renderer::CanvasRenderer;
+use crate::change_set::ChangeLog;
+use crate::cell_grid::CellGrid;
+use crate::rng::Rng;
+use crate::occupancy::Occupancy;
+use crate::field_json::FieldJson;
+
+pub use crate::error::DlaError;
+pub use crate::colorized_point::{AgentState, ColorizedPoint};
+pub use crate::history::History;
+pub use crate::replay::{Replay, ReplayConfig, verify_replay};
+pub use crate::autosave::{Autosave, AutosaveSink};
+pub use crate::png::PngOptions;
+pub use crate::svg::SvgOptions;
+pub use crate::gcode::GcodeOptions;
+pub use crate::dxf::DxfOptions;
+pub use crate::stl::StlOptions;
+pub use crate::arrival::ArrivalTimes;
+pub use crate::change_set::ChangeSet;
+pub use crate::bitmap_import::{BitmapCell, BitmapLegend};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::autosave::{RotatingFiles, latest_autosave};
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -27,15 +79,26 @@ use crate::field_position::FieldState;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+// Largest dense field, width * height. Dense fields set aside a position and a lookup entry for
+// every cell up front, which comes to about a gigabyte at this size
+const MAX_DENSE_CELLS: usize = 1 << 24;
+// Largest width or height of a sparse field, width * height has to fit in a usize as well
+const MAX_SPARSE_SIDE: usize = 1 << 24;
+
 #[wasm_bindgen]
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "FieldJson")]
 pub struct DLAField {
     width: usize,
     height: usize,
     canvas_id: String,
     agents: Vec<ColorizedPoint>,
-    agent_position_lookup: Vec<Vec<Option<usize>>>,
-    position_hash: Vec<FieldPosition>
+    agent_position_lookup: CellGrid<Option<usize>>,
+    position_hash: CellGrid<FieldPosition>,
+    occupancy: Occupancy,
+    track_changes: bool,
+    changes: ChangeLog,
+    rng: Rng
 }
 
 // === Static Methods ===
@@ -43,84 +106,155 @@ pub struct DLAField {
 impl DLAField {
 
     #[wasm_bindgen(constructor)]
-    pub fn new(canvas_id: String, num_agents: usize, width: usize, height: usize) -> DLAField {
-        let mut position_hash = DLAField::generateEmptyPositionHash(width, height);
-        let mut agent_position_lookup = vec![vec![None; height]; width];
+    pub fn new(canvas_id: String, num_agents: usize, width: usize, height: usize) -> Result<DLAField, DlaError> {
+        DLAField::with_random_agents(canvas_id, num_agents, width, height, false, Rng::from_entropy())
+    }
+
+    // same as new, but placement and every step after it are reproducible for a given seed
+    pub fn new_seeded(canvas_id: String, num_agents: usize, width: usize, height: usize, seed: u32) -> Result<DLAField, DlaError> {
+        DLAField::with_random_agents(canvas_id, num_agents, width, height, false, Rng::new(seed as u64))
+    }
+
+    // Same as new, but cells are kept in 64x64 tiles that only get allocated where agents are
+    // and are dropped again once they empty out. The field still has the width and height it was
+    // made with and agents never leave it, the plane doesn't grow. Memory and the work per tick
+    // follow the agents though, so bounds far larger than the cluster will ever reach cost
+    // nothing until something gets there
+    pub fn new_sparse(canvas_id: String, num_agents: usize, width: usize, height: usize) -> Result<DLAField, DlaError> {
+        DLAField::with_random_agents(canvas_id, num_agents, width, height, true, Rng::from_entropy())
+    }
+
+    fn with_random_agents(
+        canvas_id: String,
+        num_agents: usize,
+        width: usize,
+        height: usize,
+        sparse: bool,
+        mut rng: Rng
+    ) -> Result<DLAField, DlaError> {
+        DLAField::check_dimensions(width, height, sparse)?;
+
+        // placement keeps retrying until it finds a free cell, which never happens on a full field
+        if let Some(capacity) = width.checked_mul(height) {
+            if num_agents > capacity {
+                return Err(DlaError::TooManyAgents { num_agents, capacity });
+            }
+        }
+
+        let mut occupancy = Occupancy::new(width, height, sparse);
         let mut agents: Vec<ColorizedPoint> = [].to_vec();
 
-        for i in 0..num_agents {
-            let mut x = DLAField::gen_range(0, width);
-            let mut y =  DLAField::gen_range(0, height);
+        for _ in 0..num_agents {
+            let mut x = rng.gen_range(0, width);
+            let mut y = rng.gen_range(0, height);
+
+            while DLAField::isPositionOccupied(&occupancy, x, y) {
+                x = rng.gen_range(0, width);
+                y = rng.gen_range(0, height);
+            }
+
+            occupancy.set_occupied(x, y, true);
+            agents.push(ColorizedPoint::new(x, y, Color::new(255, 0, 0, 100), None));
+        }
+
+        DLAField::from_agents(canvas_id, width, height, sparse, agents, rng)
+    }
+
+    // Builds a field around an existing set of agents, filling in the position hash, the lookup
+    // table and the occupancy bits from them. Fails if an agent is off the field or shares its
+    // cell with another one
+    fn from_agents(
+        canvas_id: String,
+        width: usize,
+        height: usize,
+        sparse: bool,
+        agents: Vec<ColorizedPoint>,
+        rng: Rng
+    ) -> Result<DLAField, DlaError> {
+        DLAField::check_dimensions(width, height, sparse)?;
+
+        let empty_position = FieldPosition::new(FieldState::EMPTY, None);
+        let (mut position_hash, mut agent_position_lookup) = if sparse {
+            (CellGrid::new_chunked(width, height, empty_position), CellGrid::new_chunked(width, height, None))
+        } else {
+            (DLAField::generateEmptyPositionHash(width, height), CellGrid::new_dense(width, height, None))
+        };
+        let mut occupancy = Occupancy::new(width, height, sparse);
+
+        for (ndx, agent) in agents.iter().enumerate() {
+            let x = agent.get_x();
+            let y = agent.get_y();
 
-            while DLAField::isPositionOccupied(&position_hash, x, y, width) {
-                x =  DLAField::gen_range(0, width);
-                y =  DLAField::gen_range(0, height);
+            if x >= width || y >= height {
+                return Err(DlaError::InvalidData(format!(
+                    "agents[{}]: ({}, {}) is outside of the {}x{} field", ndx, x, y, width, height)));
             }
 
-            let agent = ColorizedPoint::new(
-                x, y, Color::new(255, 0, 0, 100), None);
+            if let Some(other_ndx) = agent_position_lookup.get(x, y) {
+                return Err(DlaError::InvalidData(format!(
+                    "agents[{}]: ({}, {}) is already taken by agents[{}]", ndx, x, y, other_ndx)));
+            }
 
-            let ndx = DLAField::get_ndx(x, y, width);
+            let state = match agent.state {
+                AgentState::FREE => FieldState::OCCUPIED,
+                AgentState::STUCK => FieldState::STUCK
+            };
 
-            // occupy the position
-            position_hash[ndx] = FieldPosition::new(FieldState::OCCUPIED, Some(agent));
+            position_hash.set(x, y, FieldPosition::new(state, Some(*agent)));
+            agent_position_lookup.set(x, y, Some(ndx));
+            occupancy.set_occupied(x, y, true);
 
-            // store the agent
-            agent_position_lookup[agent.get_x()][agent.get_y()] = Some(agents.len());
-            agents.push(agent);
+            if let AgentState::STUCK = agent.state {
+                occupancy.set_stuck(x, y, true);
+            }
         }
 
-        DLAField {
+        Ok(DLAField {
             width,
             height,
             agents,
             position_hash,
+            occupancy,
             canvas_id,
-            agent_position_lookup
-        }
-    }
-
-    fn generateEmptyPositionHash(width: usize, height: usize) -> Vec<FieldPosition> {
-        (0..width * height).map(|ndx| {
-            FieldPosition::new(FieldState::EMPTY, None)
-        }).collect()
+            agent_position_lookup,
+            track_changes: false,
+            changes: ChangeLog::default(),
+            rng
+        })
     }
 
-    fn isPositionOccupied(positionHash: &Vec<FieldPosition>, x: usize, y: usize, width: usize) -> bool {
-        let ndx = DLAField::get_ndx(x, y, width);
-
-        let val = &positionHash[ndx];
-        match val.state {
-            FieldState::OCCUPIED => true,
-            FieldState::STUCK => true,
-            FieldState::EMPTY => false
+    // Dimensions come from callers and from saved files, so they are checked before anything gets
+    // allocated for them
+    fn check_dimensions(width: usize, height: usize, sparse: bool) -> Result<(), DlaError> {
+        let fits = match width.checked_mul(height) {
+            None => false,
+            Some(_) if sparse => width <= MAX_SPARSE_SIDE && height <= MAX_SPARSE_SIDE,
+            Some(cells) => cells <= MAX_DENSE_CELLS
+        };
+
+        if fits {
+            Ok(())
+        } else if sparse {
+            Err(DlaError::InvalidData(format!(
+                "a {}x{} sparse field is too large, each side can be at most {}", width, height, MAX_SPARSE_SIDE)))
+        } else {
+            Err(DlaError::InvalidData(format!(
+                "a {}x{} field is too large, dense fields can have at most {} cells, see new_sparse",
+                width, height, MAX_DENSE_CELLS)))
         }
     }
 
-    fn gen_range(min: usize, max: usize) -> usize {
-        let castMin = min as f64;
-        let castMax = max as f64;
-
-        let rnd: f64 = Math::random();
-        Math::floor(rnd * (castMax - castMin) + castMin) as usize
+    fn generateEmptyPositionHash(width: usize, height: usize) -> CellGrid<FieldPosition> {
+        CellGrid::new_dense(width, height, FieldPosition::new(FieldState::EMPTY, None))
     }
 
-    pub fn get_ndx(x: usize, y: usize, width: usize) -> usize {
-        x * width + y
+    fn isPositionOccupied(occupancy: &Occupancy, x: usize, y: usize) -> bool {
+        occupancy.is_blocked(x, y)
     }
 
-    fn gen_bool(prob: f64) -> bool {
-        // console::log_1(&"gen_bool - 1".into());
-        let coin: f64 = Math::random();
-
-        // console::log_2(&"gen_bool - 2".into(), &coin.into());
-        if coin <= prob {
-            // console::log_1(&"gen_bool - 3".into());
-            return true;
-        } else {
-            // console::log_1(&"gen_bool - 4".into());
-            return false;
-        }
+    // cells are laid out column by column, so the stride is the height of the field
+    pub fn get_ndx(x: usize, y: usize, height: usize) -> usize {
+        x * height + y
     }
 }
 
@@ -133,15 +267,17 @@ impl DLAField {
     }
 
     pub fn get_height(&self) -> usize {
-        self.width
+        self.height
     }
 
     pub fn get_num_agents(&self) -> usize {
         self.agents.len()
     }
 
-    pub fn get_agent_at(&self, ndx: usize) -> ColorizedPoint {
-        self.agents[ndx]
+    pub fn get_agent_at(&self, ndx: usize) -> Result<ColorizedPoint, DlaError> {
+        self.agents.get(ndx)
+            .copied()
+            .ok_or(DlaError::AgentIndexOutOfRange { ndx, num_agents: self.agents.len() })
     }
 
     fn get_agent_at_borrow(&mut self, ndx: usize) -> &mut ColorizedPoint {
@@ -155,89 +291,143 @@ impl DLAField {
         let mut cntStuck = 0;
 
         let mut new_agents: Vec<ColorizedPoint> = [].to_vec();
-        let mut new_agent_position_lookup: Vec<Vec<Option<usize>>> = vec![vec![None; self.get_height()]; self.get_width()];
-
-        for x in 0..self.width {
-            for y in (0..self.height).rev() {
-                let field_ndx = DLAField::get_ndx(x, y, self.width);
-
-                let agent_at_position = self.get_agent_at_coordinate(x, y);
-
-                match agent_at_position {
-                    Some(mut agent) => {
-                        match agent.state {
-                            AgentState::FREE => {
-                                has_next_state = true;
-
-                                let stuck = self.is_stuck(x, y, false);
-
-                                if stuck.0 {
-                                    match stuck.1 {
-                                        None => {
-                                            agent.state = AgentState::STUCK;
-                                            agent.sticky_neighbor = None
-                                        },
-                                        Some(neighbor_position) => {
-                                            // set to stuck along with the position of the neighbor that caused it to stick
-                                            agent.state = AgentState::STUCK;
-                                            agent.sticky_neighbor = Some(StickyNeighbor {
-                                                x: neighbor_position.0,
-                                                y: neighbor_position.1
-                                            });
-
-                                        }
-                                    }
+        let mut new_agent_position_lookup = self.agent_position_lookup.empty_like();
+        let mut rng = self.rng;
+
+        // stuck bits are only flipped once the tick is over, agents sticking during it shouldn't
+        // be seen by the ones that come after them until the next tick
+        let mut newly_stuck: Vec<(usize, usize)> = vec![];
+
+        for (x, y) in self.scan_order() {
+            let agent_at_position = self.get_agent_at_coordinate(x, y);
+
+            match agent_at_position {
+                Some(mut agent) => {
+                    match agent.state {
+                        AgentState::FREE => {
+                            has_next_state = true;
+
+                            let stuck = self.is_stuck(x, y, false);
+
+                            if stuck.0 {
+                                match stuck.1 {
+                                    None => {
+                                        agent.state = AgentState::STUCK;
+                                        agent.sticky_neighbor = None
+                                    },
+                                    Some(neighbor_position) => {
+                                        // set to stuck along with the position of the neighbor that caused it to stick
+                                        agent.state = AgentState::STUCK;
+                                        agent.sticky_neighbor = Some(StickyNeighbor {
+                                            x: neighbor_position.0,
+                                            y: neighbor_position.1
+                                        });
 
-                                    self.position_hash[field_ndx] =
-                                        FieldPosition::new(FieldState::STUCK, Some(agent));
-                                    // console::log_1(&"!!! should change to stuck for reals".into());
-
-                                } else {
-                                    // find the next available position
-                                    let new_position = self.findNextPosition(x, y);
-
-                                    // check that we didn't just resolve the same location
-                                    if x != new_position.0 && y != new_position.1 {
-                                        self.move_position(
-                                            &mut agent,
-                                            new_position.0,
-                                            new_position.1,
-                                            new_agents.len()
-                                        );
                                     }
                                 }
-                            },
-                            AgentState::STUCK => {}
-                        }
 
-                        // update new vector index system
-                        new_agent_position_lookup[agent.get_x()][agent.get_y()] = Some(new_agents.len());
-                        new_agents.push(agent);
+                                self.position_hash.set(x, y, FieldPosition::new(FieldState::STUCK, Some(agent)));
+                                newly_stuck.push((x, y));
+
+                                if self.track_changes {
+                                    self.changes.record_stuck(x, y, agent.sticky_neighbor);
+                                }
+                                // console::log_1(&"!!! should change to stuck for reals".into());
+
+                            } else {
+                                // find the next available position
+                                let new_position = self.findNextPosition(x, y, &mut rng);
+
+                                // check that we didn't just resolve the same location
+                                if x != new_position.0 && y != new_position.1 {
+                                    self.move_position(
+                                        &mut agent,
+                                        new_position.0,
+                                        new_position.1,
+                                        new_agents.len()
+                                    );
+                                }
+                            }
+                        },
+                        AgentState::STUCK => {}
                     }
-                    None => {}
+
+                    // update new vector index system
+                    new_agent_position_lookup.set(agent.get_x(), agent.get_y(), Some(new_agents.len()));
+                    new_agents.push(agent);
                 }
+                None => {}
             }
         }
 
+        for (x, y) in newly_stuck {
+            self.occupancy.set_stuck(x, y, true);
+        }
+
         self.agents = new_agents;
         self.agent_position_lookup = new_agent_position_lookup;
+        self.rng = rng;
+
+        #[cfg(feature = "validate_every_tick")]
+        self.validate().expect("next_state left the field inconsistent");
 
         has_next_state
     }
 
+    // Cells holding an agent in the order of a column by column scan of the field, bottom row
+    // first. Agents are kept in the order of the last scan and walkers only move one cell per
+    // tick, so they come in nearly sorted and the sort, which picks up runs that are already in
+    // order, stays close to linear in the number of agents whatever the size of the field
+    fn scan_order(&self) -> Vec<(usize, usize)> {
+        let mut occupied_cells: Vec<(usize, usize)> = self.agents.iter()
+            .map(|agent| (agent.get_x(), agent.get_y()))
+            .collect();
+        occupied_cells.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+        occupied_cells
+    }
+
+    // Start or stop recording what next_state changes. Turning it on seeds the change set with
+    // every agent currently on the field so the first take_changes is a complete picture
+    pub fn set_track_changes(&mut self, track_changes: bool) {
+        self.changes = ChangeLog::default();
+        self.track_changes = track_changes;
+
+        if !track_changes {
+            return;
+        }
+
+        for agent in self.agents.iter() {
+            match agent.state {
+                AgentState::FREE => self.changes.record_occupied(agent.get_x(), agent.get_y()),
+                AgentState::STUCK =>
+                    self.changes.record_stuck(agent.get_x(), agent.get_y(), agent.sticky_neighbor)
+            }
+        }
+
+        for (x, y) in self.walls() {
+            self.changes.record_wall(x, y);
+        }
+    }
+
+    // hands back everything recorded since the last call and starts a fresh change set
+    pub fn take_changes(&mut self) -> ChangeSet {
+        std::mem::take(&mut self.changes).into_change_set()
+    }
+
     fn find_agent_at_coordinate(&self, x: usize, y: usize) -> Option<&ColorizedPoint> {
         self.agent_iterator()
             .find(|agent| agent.get_x() == x && agent.get_y() == y)
     }
 
-    fn findNextPosition(&self, x: usize, y: usize) -> (usize, usize) {
+    fn findNextPosition(&self, x: usize, y: usize, rng: &mut Rng) -> (usize, usize) {
         // needs to be i32 to prevent overflow
         let x = x as i32;
         let y = y as i32;
 
         // console::log_1(&"nextState - 0".into());
-        let mut newX = if  DLAField::gen_bool(0.5) { x + 1 } else { x - 1 };
-        let mut newY = if  DLAField::gen_bool(0.75) { y + 1 } else { y - 1 };
+        let mut newX = if rng.gen_bool(0.5) { x + 1 } else { x - 1 };
+        let mut newY = if rng.gen_bool(0.75) { y + 1 } else { y - 1 };
 
         // console::log_3(&"findNexPos 1".into(), &newX.into(), &newY.into());
         let width = self.get_width() as i32;
@@ -245,16 +435,16 @@ impl DLAField {
 
         // TOOD (CAW): Consider pre-calculating available states and if there is just one possibility take it
         while newX < 0 || newY < 0 || newX >= width || newY >= height {
-            newX = if  DLAField::gen_bool(0.5) { x + 1 } else { x - 1 };
-            newY = if  DLAField::gen_bool(0.75) { y + 1 } else { y - 1 };
+            newX = if rng.gen_bool(0.5) { x + 1 } else { x - 1 };
+            newY = if rng.gen_bool(0.75) { y + 1 } else { y - 1 };
         }
 
         let mut attemptCount = 0;
 
-        while  DLAField::isPositionOccupied(&self.position_hash, newX as usize, newY as usize, self.width) && attemptCount <= 4 {
+        while  DLAField::isPositionOccupied(&self.occupancy, newX as usize, newY as usize) && attemptCount <= 4 {
             while newX < 0 || newY < 0 || newX >= width || newY >= height {
-                newX = if  DLAField::gen_bool(0.5) { x + 1 } else { x - 1 };
-                newY = if  DLAField::gen_bool(0.75) { y + 1 } else { y - 1 };
+                newX = if rng.gen_bool(0.5) { x + 1 } else { x - 1 };
+                newY = if rng.gen_bool(0.75) { y + 1 } else { y - 1 };
             }
 
             attemptCount += 1;
@@ -282,20 +472,22 @@ impl DLAField {
         let x = agent.get_x();
         let y= agent.get_y();
 
-        let old_field_ndx = DLAField::get_ndx(x, y, self.get_width());
-        let new_field_ndx = DLAField::get_ndx(
-            new_x, new_y, self.get_width());
-
         // update the agent
         agent.set_x(new_x);
         agent.set_y(new_y);
 
         // update old position_hash system
-        self.position_hash[old_field_ndx] =
-            FieldPosition::new(FieldState::EMPTY, None);
+        self.position_hash.set(x, y, FieldPosition::new(FieldState::EMPTY, None));
+
+        self.position_hash.set(new_x, new_y, FieldPosition::new(FieldState::OCCUPIED, Some(*agent)));
 
-        self.position_hash[new_field_ndx] =
-            FieldPosition::new(FieldState::OCCUPIED, Some(*agent));
+        self.occupancy.set_occupied(x, y, false);
+        self.occupancy.set_occupied(new_x, new_y, true);
+
+        if self.track_changes {
+            self.changes.record_vacated(x, y);
+            self.changes.record_occupied(new_x, new_y);
+        }
     }
 
     fn is_stuck(&self, _x: usize, _y: usize, recursion: bool) -> (bool, Option<(usize, usize)>) {
@@ -332,74 +524,71 @@ impl DLAField {
             return  (false, None);
         }
 
-        let mut dx: i32 = -1;
-        if x == 0 {
-            dx = 0;
-        }
+        // stuck rows y - 1, y and y + 1 for columns x - 1, x and x + 1, checked column by column
+        // and top down so the first stuck neighbor found is the same as walking them one by one
+        let around = self.occupancy.stuck_around(_x, _y);
 
-        while dx < 2 {
-            let mut dy: i32 = -1;
+        for (column, stuck_rows) in around.iter().enumerate() {
+            let neighbor_x = x + column as i32 - 1;
 
-            if y == 0 {
-                dy = 0;
+            if neighbor_x < 0 || neighbor_x >= width {
+                continue;
             }
 
-            while dy < 2 {
-                let neighbor_x = x as i32 + dx as i32;
-                let neighbor_y = y as i32 + dy as i32;
+            let mut stuck_rows = *stuck_rows;
 
-                if neighbor_x < width && neighbor_y < height &&
-                    self.is_stuck(neighbor_x as usize, neighbor_y as usize, true).0
-                {
-                    // stuck with a neighbor
-                    return (true, Some((neighbor_x as usize, neighbor_y as usize)))
-                }
-
-                dy += 1;
+            // a free agent resting on the bottom row is about to become a root so it counts too
+            if y + 1 == height - 1 &&
+                self.get_agent_at_coordinate(neighbor_x as usize, (height - 1) as usize).is_some()
+            {
+                stuck_rows |= 0b100;
             }
 
-            dx += 1
+            if stuck_rows != 0 {
+                let neighbor_y = y + stuck_rows.trailing_zeros() as i32 - 1;
+
+                // stuck with a neighbor
+                return (true, Some((neighbor_x as usize, neighbor_y as usize)))
+            }
         }
 
         (false, None)
     }
 
 
-    fn _get_distance_from_root(&self, agent: ColorizedPoint, size: usize) -> usize {
-        match agent.get_sticky_neighbor() {
-            None => {
-                0
-            },
-            Some(neighbor_position) => {
-                let neighbor_x = neighbor_position.x;
-                let neighbor_y = neighbor_position.y;
+    // Walks the sticky neighbors back to the root. Every hop adds the number of hops taken before
+    // it, so a chain of n hops comes out as n * (n - 1) / 2
+    pub fn get_distance_from_root(&self, agent: ColorizedPoint) -> Result<usize, DlaError> {
+        let mut distance = 0;
+        let mut hops = 0;
+        let mut current = agent;
 
-                let neighbor = self.get_agent_at_coordinate(neighbor_x, neighbor_y);
+        while let Some(neighbor_position) = current.get_sticky_neighbor() {
+            // a chain can't be longer than the number of agents unless it runs in a circle
+            if hops > self.agents.len() {
+                return Err(DlaError::NeighborCycle { x: agent.get_x(), y: agent.get_y() });
+            }
 
+            current = self.get_agent_at_coordinate(neighbor_position.x, neighbor_position.y)
+                .ok_or(DlaError::MissingNeighbor { x: neighbor_position.x, y: neighbor_position.y })?;
 
-                // assume there is a neighbor based on neighbor_position, if not panic
-                size + self._get_distance_from_root(neighbor.unwrap(), size + 1)
-            }
+            distance += hops;
+            hops += 1;
         }
-    }
 
-    pub fn get_distance_from_root(&self, agent: ColorizedPoint) -> usize {
-        self._get_distance_from_root(agent, 0)
+        Ok(distance)
     }
 
     fn get_agent_at_coordinate(&self, x: usize, y: usize) -> Option<ColorizedPoint> {
-        match self.agent_position_lookup[x][y] {
-            None => { None }
-            Some(ndx) => {
-                Some(self.get_agent_at(ndx))
-            }
-
+        if x >= self.width || y >= self.height {
+            return None;
         }
+
+        self.agent_position_lookup.get(x, y).map(|ndx| self.agents[ndx])
     }
 
     fn isEmpty(&self, x: u32, y: u32) -> bool {
-        let ndx = DLAField::get_ndx(x as usize, y as usize, self.get_width());
-        if let FieldState::EMPTY = self.position_hash[ndx].state {
+        if let FieldState::EMPTY = self.position_hash.get(x as usize, y as usize).state {
             return true
         }
 
@@ -408,42 +597,26 @@ impl DLAField {
 
     // this is more for testing
     pub fn getOccpupiedCount(&self) -> u32 {
-        let mut cnt = 0;
-
-        for ndx in 0..self.position_hash.len() {
-            match &self.position_hash[ndx].state {
-                FieldState::EMPTY => {},
-                FieldState::OCCUPIED => {
-                    cnt += 1;
-                },
-                FieldState::STUCK => {
-                    cnt += 1;
-                }
-            }
-        }
+        self.occupancy.occupied_count() as u32
+    }
+
+    // the position hash as one array in wasm memory, sparse fields don't have one
+    pub fn get_position_hash(&self) -> Result<*const FieldPosition, DlaError> {
+        self.position_hash.as_ptr().ok_or(DlaError::SparseField)
+    }
 
-        cnt
+    pub fn is_sparse(&self) -> bool {
+        self.position_hash.is_chunked()
     }
 
-    pub fn get_position_hash(&self) -> *const FieldPosition {
-        self.position_hash.as_ptr()
+    // number of cells backed by memory across the position hash, handy for gauging sparse fields
+    pub fn get_allocated_cells(&self) -> usize {
+        self.position_hash.allocated_len()
     }
 
     // this is more for testing
     pub fn getStuckCount(&self) -> u32 {
-        let mut cnt = 0;
-
-        for ndx in 0..self.position_hash.len() {
-            match &self.position_hash[ndx].state {
-                FieldState::EMPTY => {},
-                FieldState::OCCUPIED => {},
-                FieldState::STUCK => {
-                    cnt +=  1;
-                }
-            }
-        }
-
-        cnt
+        self.occupancy.stuck_count() as u32
     }
 }
 
@@ -452,75 +625,8 @@ pub struct DLAFieldRenders {}
 
 #[wasm_bindgen]
 impl DLAFieldRenders {
-    pub fn draw(dla_field: &DLAField, canvas_id: String) {
-        let document = web_sys::window().unwrap().document().unwrap();
-        let canvas = document.get_element_by_id(&canvas_id).unwrap();
-        let stuckSize = 1.0;
-        let seedSize = 1.0;
-
-        let canvas: web_sys::HtmlCanvasElement = canvas
-            .dyn_into::<web_sys::HtmlCanvasElement>()
-            .map_err(|_| ())
-            .unwrap();
-
-        let context = canvas
-            .get_context("2d")
-            .unwrap()
-            .unwrap()
-            .dyn_into::<web_sys::CanvasRenderingContext2d>()
-            .unwrap();
-
-        let width = canvas.width();
-        let height = canvas.height();
-
-        context.clear_rect(0.0, 0.0, canvas.width().into(), canvas.height().into());
-
-        for ndx in 0..dla_field.position_hash.len() as u32 {
-            let y = (ndx % width);
-            let x = (ndx / height);
-
-            let position: FieldPosition = dla_field.position_hash[ndx as usize];
-            match position.state {
-                FieldState::EMPTY => {},
-                FieldState::STUCK => {
-                    context.set_fill_style(&"rgba(255, 0, 0, 255)".into());
-                    context.fill_rect(x as f64 * stuckSize, y as f64 * stuckSize, stuckSize, stuckSize);
-                },
-                FieldState::OCCUPIED => {
-                    context.set_fill_style(&"rgba(255, 0, 0, 255)".into());
-                    context.fill_rect(x as f64 * seedSize, y as f64 * seedSize, seedSize, seedSize);
-                }
-            }
-        }
+    // one-off draw, callers rendering every frame should hold on to a CanvasRenderer instead
+    pub fn draw(dla_field: &DLAField, canvas_id: String) -> Result<(), DlaError> {
+        CanvasRenderer::new(canvas_id)?.draw(dla_field)
     }
 }
-
-#[wasm_bindgen]
-pub fn build_field_from_js_state(canvas_id: String, width: usize, height: usize, agents: Array ) -> DLAField {
-    console::log_1(&"initialize a new field".into());
-    let mut new_field = DLAField::new(canvas_id, agents.length() as usize, width, height);
-
-    // even this fails with a runtime exception, much boo :-(
-    // let test: ColorizedPoint = agents.get(0).into_serde().unwrap();
-
-    console::log_1(&"deserialize agents".into());
-    let agents_vec : Vec<ColorizedPoint> = agents.into_serde().unwrap();
-
-    console::log_1(&"clone incoming to the agents field".into());
-    new_field.agents = agents_vec.clone();
-
-    // build the location based lookup table
-    let mut agent_position_lookup: Vec<Vec<Option<usize>>> = vec![vec![None; height]; width];
-
-    console::log_1(&"build lookup table".into());
-    for i in 0..new_field.get_num_agents() {
-        let agent = new_field.get_agent_at(i);
-        let x = agent.get_x();
-        let y = agent.get_y();
-
-        agent_position_lookup[x][y] = Some(i);
-    }
-
-    console::log_1(&"return final value".into());
-    new_field
-}
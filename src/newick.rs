@@ -0,0 +1,97 @@
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::tree::AggregateTree;
+use crate::error::DlaError;
+use crate::utils::format_decimal;
+
+enum Visit {
+    // node, length of the branch leading up to it
+    Enter(usize, Option<f64>),
+    Separator,
+    Exit(usize, Option<f64>)
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    // The stuck agents as Newick trees, one line per root cell in field order. Nodes are labeled
+    // x<x>y<y> after their cell and branch lengths are the grid distance to the parent, 1 or the
+    // square root of 2 for diagonals. collapse_chains folds agents with a single child into the
+    // branch below them, summing up the lengths, so only roots, forks and tips are left
+    pub fn export_newick(&self, collapse_chains: bool) -> Result<String, DlaError> {
+        let tree = self.aggregate_tree()?;
+        let mut newick = String::new();
+
+        for &root in tree.roots.iter() {
+            // trees can be thousands of agents deep, so walk them without recursion
+            let mut stack = vec![Visit::Enter(root, None)];
+
+            while let Some(visit) = stack.pop() {
+                match visit {
+                    Visit::Enter(ndx, length) => {
+                        let branches = branches(&tree, ndx, collapse_chains);
+
+                        if branches.is_empty() {
+                            push_node(&mut newick, &tree, ndx, length);
+                            continue;
+                        }
+
+                        newick.push('(');
+                        stack.push(Visit::Exit(ndx, length));
+                        for (position, &(child, child_length)) in branches.iter().enumerate().rev() {
+                            stack.push(Visit::Enter(child, Some(child_length)));
+                            if position > 0 {
+                                stack.push(Visit::Separator);
+                            }
+                        }
+                    },
+                    Visit::Separator => newick.push(','),
+                    Visit::Exit(ndx, length) => {
+                        newick.push(')');
+                        push_node(&mut newick, &tree, ndx, length);
+                    }
+                }
+            }
+
+            newick.push_str(";\n");
+        }
+
+        Ok(newick)
+    }
+}
+
+// the children of a node with the length of the branch to each, following single child chains
+// to their end when collapsing
+fn branches(tree: &AggregateTree, ndx: usize, collapse_chains: bool) -> Vec<(usize, f64)> {
+    tree.children[ndx].iter()
+        .map(|&child| {
+            let mut end = child;
+            let mut length = grid_distance(tree, ndx, child);
+
+            while collapse_chains && tree.children[end].len() == 1 {
+                let next = tree.children[end][0];
+                length += grid_distance(tree, end, next);
+                end = next;
+            }
+
+            (end, length)
+        })
+        .collect()
+}
+
+fn grid_distance(tree: &AggregateTree, from: usize, to: usize) -> f64 {
+    let dx = tree.nodes[from].x as f64 - tree.nodes[to].x as f64;
+    let dy = tree.nodes[from].y as f64 - tree.nodes[to].y as f64;
+
+    (dx * dx + dy * dy).sqrt()
+}
+
+fn push_node(newick: &mut String, tree: &AggregateTree, ndx: usize, length: Option<f64>) {
+    let node = tree.nodes[ndx];
+    newick.push_str(&format!("x{}y{}", node.x, node.y));
+
+    if let Some(length) = length {
+        newick.push(':');
+        newick.push_str(&format_decimal(length, 6));
+    }
+}
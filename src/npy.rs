@@ -0,0 +1,229 @@
+use std::convert::TryFrom;
+
+use miniz_oxide::deflate::compress_to_vec;
+use wasm_bindgen::prelude::*;
+
+use crate::{DLAField, MAX_DENSE_CELLS};
+use crate::arrival::ArrivalTimes;
+use crate::bytes::*;
+use crate::error::DlaError;
+use crate::field_position::FieldState;
+
+// NumPy arrays, for analysis in Python with np.load. Every array is a version 1.0 .npy file in C
+// order, grids are indexed [y, x] with row 0 at the top like on the canvas so plt.imshow shows
+// them the right way up:
+//
+//   cells     uint8 (height, width)  0 empty, 1 free agent, 2 stuck, 3 wall
+//   depth     int32 (height, width)  hops from a stuck agent to its root, -1 where nothing is stuck
+//   arrival   int32 (height, width)  tick a stuck agent arrived at, see ArrivalTimes, -1 elsewhere
+//   agents    int32 (agents, 5)      x, y, parent x, parent y, species per agent in field order,
+//                                    parents are -1 for free agents and roots. The field only has
+//                                    the one species so far, which is 0
+//
+// export_npz bundles all four as cells.npy, depth.npy, arrival.npy and agents.npy in a deflated
+// zip, the same as np.savez_compressed writes. The grids spell out every cell, so they are only
+// written for fields of at most MAX_DENSE_CELLS cells, anything a sparse field adds past that fails
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+// the header is padded so the data starts on a 64 byte boundary
+const NPY_ALIGNMENT: usize = 64;
+
+const ZIP_VERSION: u16 = 20;
+const ZIP_DEFLATE: u16 = 8;
+// 1980-01-01 00:00, the earliest date zip can hold
+const ZIP_DATE: u16 = (1 << 5) | 1;
+const DEFLATE_LEVEL: u8 = 6;
+
+const NO_VALUE: i32 = -1;
+
+const CELL_EMPTY: u8 = 0;
+const CELL_FREE: u8 = 1;
+const CELL_STUCK: u8 = 2;
+const CELL_WALL: u8 = 3;
+
+const SPECIES: i32 = 0;
+
+#[wasm_bindgen]
+impl DLAField {
+    pub fn export_npy_cells(&self) -> Result<Vec<u8>, DlaError> {
+        let mut cells = Vec::with_capacity(grid_cells(self.width, self.height)?);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                cells.push(match self.position_hash.get(x, y).state {
+                    FieldState::EMPTY => CELL_EMPTY,
+                    FieldState::OCCUPIED => CELL_FREE,
+                    FieldState::STUCK => CELL_STUCK,
+                    FieldState::WALL => CELL_WALL
+                });
+            }
+        }
+
+        Ok(npy("|u1", &[self.height, self.width], &cells))
+    }
+
+    pub fn export_npy_depth(&self) -> Result<Vec<u8>, DlaError> {
+        let mut depths = vec![NO_VALUE; grid_cells(self.width, self.height)?];
+        let tree = self.aggregate_tree()?;
+
+        for node in tree.nodes.iter() {
+            depths[node.y * self.width + node.x] = node.depth as i32;
+        }
+
+        Ok(npy_i32(&[self.height, self.width], &depths))
+    }
+
+    pub fn export_npy_agents(&self) -> Vec<u8> {
+        let mut table = Vec::with_capacity(self.agents.len() * 5);
+
+        for agent in self.agents.iter() {
+            let (parent_x, parent_y) = match agent.sticky_neighbor {
+                Some(neighbor) => (neighbor.x as i32, neighbor.y as i32),
+                None => (NO_VALUE, NO_VALUE)
+            };
+
+            table.extend_from_slice(&[agent.get_x() as i32, agent.get_y() as i32, parent_x, parent_y, SPECIES]);
+        }
+
+        npy_i32(&[self.agents.len(), 5], &table)
+    }
+
+    // arrivals has to have been recorded on this field, pass a fresh ArrivalTimes when there is
+    // nothing recorded and every stuck agent comes out as tick 0
+    pub fn export_npz(&self, arrivals: &ArrivalTimes) -> Result<Vec<u8>, DlaError> {
+        if arrivals.ticks().dimensions() != (self.width, self.height) {
+            return Err(DlaError::InvalidData("arrivals: recorded on a field of a different size".to_string()));
+        }
+
+        let files = [
+            ("cells.npy", self.export_npy_cells()?),
+            ("depth.npy", self.export_npy_depth()?),
+            ("arrival.npy", arrivals.export_npy()?),
+            ("agents.npy", self.export_npy_agents())
+        ];
+
+        zip(&files)
+    }
+}
+
+#[wasm_bindgen]
+impl ArrivalTimes {
+    pub fn export_npy(&self) -> Result<Vec<u8>, DlaError> {
+        let (width, height) = self.ticks().dimensions();
+        let mut ticks = Vec::with_capacity(grid_cells(width, height)?);
+
+        for y in 0..height {
+            for x in 0..width {
+                ticks.push(self.ticks().get(x, y));
+            }
+        }
+
+        Ok(npy_i32(&[height, width], &ticks))
+    }
+}
+
+// the number of cells in a grid export, as long as it stays within what a dense field can hold
+fn grid_cells(width: usize, height: usize) -> Result<usize, DlaError> {
+    match width.checked_mul(height) {
+        Some(cells) if cells <= MAX_DENSE_CELLS => Ok(cells),
+        _ => Err(DlaError::InvalidData(format!(
+            "npy: a {}x{} grid is too large, grids are only written for up to {} cells", width, height, MAX_DENSE_CELLS)))
+    }
+}
+
+fn npy_i32(shape: &[usize], values: &[i32]) -> Vec<u8> {
+    let data: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+
+    npy("<i4", shape, &data)
+}
+
+// magic, version 1.0, the header length and a python dict literal describing the array
+fn npy(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let shape: Vec<String> = shape.iter().map(|len| len.to_string()).collect();
+    // a one element tuple needs its trailing comma
+    let shape = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!("({})", shape.join(", "))
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+
+    // magic, version and the header length take 10 bytes, the header ends in a newline
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    let padding = (NPY_ALIGNMENT - unpadded % NPY_ALIGNMENT) % NPY_ALIGNMENT;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut npy = Vec::with_capacity(NPY_MAGIC.len() + 4 + header.len() + data.len());
+    npy.extend_from_slice(NPY_MAGIC);
+    npy.extend_from_slice(&[1, 0]);
+    push_u16(&mut npy, header.len() as u16);
+    npy.extend_from_slice(header.as_bytes());
+    npy.extend_from_slice(data);
+
+    npy
+}
+
+// A zip archive without zip64, so every file and the whole archive have to stay below 4GB
+fn zip(files: &[(&str, Vec<u8>)]) -> Result<Vec<u8>, DlaError> {
+    let too_big = || DlaError::InvalidData("npz: zip archives without zip64 are limited to 4GB".to_string());
+    let mut archive = vec![];
+    let mut directory = vec![];
+
+    for (name, contents) in files.iter() {
+        let offset = u32::try_from(archive.len()).map_err(|_| too_big())?;
+        let size = u32::try_from(contents.len()).map_err(|_| too_big())?;
+        let compressed = compress_to_vec(contents, DEFLATE_LEVEL);
+        let compressed_size = u32::try_from(compressed.len()).map_err(|_| too_big())?;
+        let crc = crc32(contents);
+
+        // local file header
+        push_u32(&mut archive, 0x0403_4b50);
+        push_u16(&mut archive, ZIP_VERSION);
+        push_u16(&mut archive, 0);
+        push_u16(&mut archive, ZIP_DEFLATE);
+        push_u16(&mut archive, 0);
+        push_u16(&mut archive, ZIP_DATE);
+        push_u32(&mut archive, crc);
+        push_u32(&mut archive, compressed_size);
+        push_u32(&mut archive, size);
+        push_u16(&mut archive, name.len() as u16);
+        push_u16(&mut archive, 0);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&compressed);
+
+        // central directory entry pointing back at it
+        push_u32(&mut directory, 0x0201_4b50);
+        push_u16(&mut directory, ZIP_VERSION);
+        push_u16(&mut directory, ZIP_VERSION);
+        push_u16(&mut directory, 0);
+        push_u16(&mut directory, ZIP_DEFLATE);
+        push_u16(&mut directory, 0);
+        push_u16(&mut directory, ZIP_DATE);
+        push_u32(&mut directory, crc);
+        push_u32(&mut directory, compressed_size);
+        push_u32(&mut directory, size);
+        push_u16(&mut directory, name.len() as u16);
+        // extra field, comment, disk, internal and external attributes
+        push_u16(&mut directory, 0);
+        push_u16(&mut directory, 0);
+        push_u16(&mut directory, 0);
+        push_u16(&mut directory, 0);
+        push_u32(&mut directory, 0);
+        push_u32(&mut directory, offset);
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = u32::try_from(archive.len()).map_err(|_| too_big())?;
+    archive.extend_from_slice(&directory);
+
+    // end of central directory, everything on disk 0 and no comment
+    push_u32(&mut archive, 0x0605_4b50);
+    push_u16(&mut archive, 0);
+    push_u16(&mut archive, 0);
+    push_u16(&mut archive, files.len() as u16);
+    push_u16(&mut archive, files.len() as u16);
+    push_u32(&mut archive, directory.len() as u32);
+    push_u32(&mut archive, directory_offset);
+    push_u16(&mut archive, 0);
+
+    Ok(archive)
+}
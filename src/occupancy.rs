@@ -0,0 +1,140 @@
+use crate::cell_grid::CellGrid;
+
+const WORD_BITS: usize = 64;
+
+// Packed bits marking which cells hold an agent, which of those are stuck and which cells are
+// walls. Every column of the field is split into 64 cell words, so a cell and its vertical
+// neighbours are almost always in the same word. Words are kept in a CellGrid of the same flavour
+// as the field so sparse fields stay sparse
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Occupancy {
+    width: usize,
+    occupied: CellGrid<u64>,
+    stuck: CellGrid<u64>,
+    walls: CellGrid<u64>,
+    occupied_count: usize,
+    stuck_count: usize,
+    wall_count: usize
+}
+
+impl Occupancy {
+    pub fn new(width: usize, height: usize, sparse: bool) -> Occupancy {
+        let words_per_column = height.div_ceil(WORD_BITS);
+        let words = if sparse {
+            CellGrid::new_chunked(width, words_per_column, 0)
+        } else {
+            CellGrid::new_dense(width, words_per_column, 0)
+        };
+
+        Occupancy {
+            width,
+            occupied: words.clone(),
+            stuck: words.clone(),
+            walls: words,
+            occupied_count: 0,
+            stuck_count: 0,
+            wall_count: 0
+        }
+    }
+
+    pub fn is_occupied(&self, x: usize, y: usize) -> bool {
+        Occupancy::get_bit(&self.occupied, x, y)
+    }
+
+    pub fn is_stuck(&self, x: usize, y: usize) -> bool {
+        Occupancy::get_bit(&self.stuck, x, y)
+    }
+
+    pub fn is_wall(&self, x: usize, y: usize) -> bool {
+        Occupancy::get_bit(&self.walls, x, y)
+    }
+
+    // nothing can move into an agent or a wall
+    pub fn is_blocked(&self, x: usize, y: usize) -> bool {
+        self.is_occupied(x, y) || self.is_wall(x, y)
+    }
+
+    pub fn set_occupied(&mut self, x: usize, y: usize, occupied: bool) {
+        Occupancy::set_bit(&mut self.occupied, &mut self.occupied_count, x, y, occupied);
+    }
+
+    pub fn set_stuck(&mut self, x: usize, y: usize, stuck: bool) {
+        Occupancy::set_bit(&mut self.stuck, &mut self.stuck_count, x, y, stuck);
+    }
+
+    pub fn set_wall(&mut self, x: usize, y: usize, wall: bool) {
+        Occupancy::set_bit(&mut self.walls, &mut self.wall_count, x, y, wall);
+    }
+
+    pub fn occupied_count(&self) -> usize {
+        self.occupied_count
+    }
+
+    pub fn stuck_count(&self) -> usize {
+        self.stuck_count
+    }
+
+    pub fn wall_count(&self) -> usize {
+        self.wall_count
+    }
+
+    // For columns x - 1, x and x + 1, three bits each saying which of rows y - 1, y and y + 1 are
+    // stuck, row y - 1 in the lowest bit. Cells off the edge of the field read as not stuck
+    pub fn stuck_around(&self, x: usize, y: usize) -> [u8; 3] {
+        let mut around = [0; 3];
+
+        for (column, rows) in around.iter_mut().enumerate() {
+            let neighbor_x = x + column;
+
+            if neighbor_x == 0 || neighbor_x > self.width {
+                continue;
+            }
+
+            *rows = self.column_window(&self.stuck, neighbor_x - 1, y);
+        }
+
+        around
+    }
+
+    // rows y - 1 to y + 1 of one column, read from at most two words
+    fn column_window(&self, words: &CellGrid<u64>, x: usize, y: usize) -> u8 {
+        let (_, words_per_column) = words.dimensions();
+        let top = y.saturating_sub(1);
+        let word_ndx = top / WORD_BITS;
+
+        let mut window = words.get(x, word_ndx) as u128;
+        if word_ndx + 1 < words_per_column {
+            window |= (words.get(x, word_ndx + 1) as u128) << WORD_BITS;
+        }
+
+        let rows = if y == 0 {
+            // there is no row above the first one, shift in an empty bit for it
+            window << 1
+        } else {
+            window >> (top % WORD_BITS)
+        };
+
+        (rows & 0b111) as u8
+    }
+
+    fn get_bit(words: &CellGrid<u64>, x: usize, y: usize) -> bool {
+        words.get(x, y / WORD_BITS) & (1 << (y % WORD_BITS)) != 0
+    }
+
+    fn set_bit(words: &mut CellGrid<u64>, count: &mut usize, x: usize, y: usize, value: bool) {
+        let word = words.get(x, y / WORD_BITS);
+        let mask = 1 << (y % WORD_BITS);
+
+        match (word & mask != 0, value) {
+            (false, true) => {
+                words.set(x, y / WORD_BITS, word | mask);
+                *count += 1;
+            },
+            (true, false) => {
+                words.set(x, y / WORD_BITS, word & !mask);
+                *count -= 1;
+            },
+            _ => {}
+        }
+    }
+}
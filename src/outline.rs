@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use crate::DLAField;
+use crate::colorized_point::AgentState;
+use crate::error::DlaError;
+
+// Corner of a cell on the lattice the cells sit on, (x, y) with y pointing up: cell (x, y) of the
+// field covers x..x + 1 and height - y - 1..height - y
+pub type LatticePoint = (usize, usize);
+
+impl DLAField {
+    // Outlines of the stuck cells as closed polygons on the cell lattice, without the closing
+    // point repeated and only keeping the corners. Outer boundaries run counter clockwise and
+    // holes clockwise, so the stuck cells are always on the left. Cells that only touch at a
+    // corner get separate outlines
+    pub fn stuck_outlines(&self) -> Result<Vec<Vec<LatticePoint>>, DlaError> {
+        let height = self.height;
+        let is_stuck = |x: i64, y: i64| {
+            x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < height &&
+                self.occupancy.is_stuck(x as usize, y as usize)
+        };
+
+        // directed boundary edges, keyed by where they start
+        let mut edges: HashMap<LatticePoint, Vec<LatticePoint>> = HashMap::new();
+        let mut num_edges = 0;
+
+        for agent in self.agents.iter().filter(|agent| agent.state == AgentState::STUCK) {
+            let (x, y) = (agent.get_x(), agent.get_y());
+            let (left, right, bottom, top) = (x, x + 1, height - y - 1, height - y);
+            let (cx, cy) = (x as i64, y as i64);
+
+            let mut sides = vec![];
+            if !is_stuck(cx, cy + 1) {
+                sides.push(((left, bottom), (right, bottom)));
+            }
+            if !is_stuck(cx + 1, cy) {
+                sides.push(((right, bottom), (right, top)));
+            }
+            if !is_stuck(cx, cy - 1) {
+                sides.push(((right, top), (left, top)));
+            }
+            if !is_stuck(cx - 1, cy) {
+                sides.push(((left, top), (left, bottom)));
+            }
+
+            for (from, to) in sides {
+                edges.entry(from).or_default().push(to);
+                num_edges += 1;
+            }
+        }
+
+        let mut outlines = vec![];
+        let mut starts: Vec<LatticePoint> = edges.keys().copied().collect();
+        starts.sort_unstable();
+
+        for start in starts {
+            while edges.get(&start).is_some_and(|ends| !ends.is_empty()) {
+                let mut outline = vec![start];
+                let mut from = start;
+                let mut to = take_edge(&mut edges, from, None)?;
+                num_edges -= 1;
+
+                while to != start {
+                    outline.push(to);
+                    let next = take_edge(&mut edges, to, Some(from))?;
+                    num_edges -= 1;
+                    from = to;
+                    to = next;
+                }
+
+                outlines.push(only_corners(outline));
+            }
+        }
+
+        debug_assert_eq!(num_edges, 0);
+
+        Ok(outlines)
+    }
+}
+
+// Follows the boundary out of a point. Where two outlines meet at a corner there are two ways
+// out, turning left keeps to the cell the outline came along. Boundary edges of a consistent field
+// always form closed loops, running out of them means the stuck bits and agents disagree
+fn take_edge(
+    edges: &mut HashMap<LatticePoint, Vec<LatticePoint>>,
+    at: LatticePoint,
+    came_from: Option<LatticePoint>
+) -> Result<LatticePoint, DlaError> {
+    let ends = match edges.get_mut(&at) {
+        Some(ends) if !ends.is_empty() => ends,
+        _ => return Err(DlaError::InvalidData(format!(
+            "outline: the boundary does not close, no edge leaves ({}, {})", at.0, at.1)))
+    };
+
+    let ndx = match (came_from, ends.len()) {
+        (Some(from), 2) => {
+            let incoming = (at.0 as i64 - from.0 as i64, at.1 as i64 - from.1 as i64);
+            ends.iter()
+                .position(|end| {
+                    let outgoing = (end.0 as i64 - at.0 as i64, end.1 as i64 - at.1 as i64);
+                    // positive cross product is a left turn
+                    incoming.0 * outgoing.1 - incoming.1 * outgoing.0 > 0
+                })
+                .unwrap_or(0)
+        },
+        _ => 0
+    };
+
+    Ok(ends.swap_remove(ndx))
+}
+
+// drops the points in the middle of straight runs
+fn only_corners(outline: Vec<LatticePoint>) -> Vec<LatticePoint> {
+    let len = outline.len();
+
+    (0..len)
+        .filter(|&ndx| {
+            let previous = outline[(ndx + len - 1) % len];
+            let point = outline[ndx];
+            let next = outline[(ndx + 1) % len];
+
+            let incoming = (point.0 as i64 - previous.0 as i64, point.1 as i64 - previous.1 as i64);
+            let outgoing = (next.0 as i64 - point.0 as i64, next.1 as i64 - point.1 as i64);
+            incoming != outgoing
+        })
+        .map(|ndx| outline[ndx])
+        .collect()
+}
@@ -0,0 +1,33 @@
+use crate::field_position::FieldState;
+
+const EMPTY_COLOR: [u8; 4] = [0, 0, 0, 0];
+const STUCK_COLOR: [u8; 4] = [255, 0, 0, 255];
+const OCCUPIED_COLOR: [u8; 4] = [255, 0, 0, 255];
+const WALL_COLOR: [u8; 4] = [128, 128, 128, 255];
+
+// RGBA color for each state a cell can be in. The default is what the canvas renderer draws, so
+// exports come out looking the same as the page
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub empty: [u8; 4],
+    pub occupied: [u8; 4],
+    pub stuck: [u8; 4],
+    pub wall: [u8; 4]
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette { empty: EMPTY_COLOR, occupied: OCCUPIED_COLOR, stuck: STUCK_COLOR, wall: WALL_COLOR }
+    }
+}
+
+impl Palette {
+    pub fn color_for(&self, state: FieldState) -> [u8; 4] {
+        match state {
+            FieldState::EMPTY => self.empty,
+            FieldState::OCCUPIED => self.occupied,
+            FieldState::STUCK => self.stuck,
+            FieldState::WALL => self.wall
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::thread;
+
+use crate::DLAField;
+use crate::colorized_point::{AgentState, StickyNeighbor};
+use crate::field_position::{FieldPosition, FieldState};
+use crate::rng::Rng;
+
+// what an agent is going to do this tick, decided against the field as it was when the tick started
+enum Step {
+    Stay,
+    Stick(Option<StickyNeighbor>),
+    MoveTo(usize, usize)
+}
+
+impl DLAField {
+    // Native only counterpart to next_state that spreads the work over threads. The agents, in
+    // scan order, are cut into num_threads runs of about the same length, so every worker gets
+    // the same share whether the cluster fills the field or sits in one corner of it. Every free
+    // agent decides, concurrently, whether it sticks or where it walks based on the field as it
+    // was at the start of the tick. The decisions are then applied in the usual scan order, a
+    // walker whose target was taken by an earlier one in the same tick stays put. Each agent
+    // draws from its own generator keyed off the field's rng and its cell, so the outcome depends
+    // on the seed alone and never on the thread count. It does not match next_state step for
+    // step, that one lets later walkers see earlier moves mid tick
+    pub fn next_state_parallel(&mut self, num_threads: usize) -> bool {
+        let tick_key = self.rng.next_u64();
+        let cells = self.scan_order();
+        let run_len = cells.len().div_ceil(num_threads.max(1)).max(1);
+
+        let field = &*self;
+        let decide = move |run: &[(usize, usize)]| -> Vec<Step> {
+            run.iter().map(|&(x, y)| field.decide_step(x, y, tick_key)).collect()
+        };
+
+        // the calling thread takes the first run, so num_threads counts it as one of the workers
+        let steps: Vec<Step> = thread::scope(|scope| {
+            let mut runs = cells.chunks(run_len);
+            let first = runs.next().unwrap_or(&[]);
+            let handles: Vec<_> = runs
+                .map(|run| scope.spawn(move || decide(run)))
+                .collect();
+
+            let mut steps = decide(first);
+            for handle in handles {
+                steps.extend(handle.join().unwrap());
+            }
+
+            steps
+        });
+
+        let mut has_next_state = false;
+        let mut new_agents = vec![];
+        let mut new_agent_position_lookup = self.agent_position_lookup.empty_like();
+
+        for (&(x, y), step) in cells.iter().zip(steps) {
+            let mut agent = match self.get_agent_at_coordinate(x, y) {
+                None => continue,
+                Some(agent) => agent
+            };
+
+            if let AgentState::FREE = agent.state {
+                has_next_state = true;
+            }
+
+            match step {
+                Step::Stay => {},
+                Step::Stick(sticky_neighbor) => {
+                    agent.state = AgentState::STUCK;
+                    agent.sticky_neighbor = sticky_neighbor;
+
+                    self.position_hash.set(x, y, FieldPosition::new(FieldState::STUCK, Some(agent)));
+                    self.occupancy.set_stuck(x, y, true);
+
+                    if self.track_changes {
+                        self.changes.record_stuck(x, y, sticky_neighbor);
+                    }
+                },
+                Step::MoveTo(new_x, new_y) => {
+                    if !DLAField::isPositionOccupied(&self.occupancy, new_x, new_y) {
+                        self.move_position(&mut agent, new_x, new_y, new_agents.len());
+                    }
+                }
+            }
+
+            new_agent_position_lookup.set(agent.get_x(), agent.get_y(), Some(new_agents.len()));
+            new_agents.push(agent);
+        }
+
+        self.agents = new_agents;
+        self.agent_position_lookup = new_agent_position_lookup;
+
+        #[cfg(feature = "validate_every_tick")]
+        self.validate().expect("next_state_parallel left the field inconsistent");
+
+        has_next_state
+    }
+
+    fn decide_step(&self, x: usize, y: usize, tick_key: u64) -> Step {
+        let agent = match self.get_agent_at_coordinate(x, y) {
+            None => return Step::Stay,
+            Some(agent) => agent
+        };
+
+        if let AgentState::STUCK = agent.state {
+            return Step::Stay;
+        }
+
+        let stuck = self.is_stuck(x, y, false);
+        if stuck.0 {
+            return Step::Stick(stuck.1.map(|(neighbor_x, neighbor_y)| {
+                StickyNeighbor::new(neighbor_x, neighbor_y)
+            }));
+        }
+
+        let mut rng = Rng::new(tick_key ^ Rng::new(((x as u64) << 32) | y as u64).next_u64());
+        let new_position = self.findNextPosition(x, y, &mut rng);
+
+        // same rule as next_state for whether the walker actually goes anywhere
+        if x != new_position.0 && y != new_position.1 {
+            Step::MoveTo(new_position.0, new_position.1)
+        } else {
+            Step::Stay
+        }
+    }
+}
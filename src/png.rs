@@ -0,0 +1,135 @@
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::bytes::crc32;
+use crate::palette::Palette;
+use crate::error::DlaError;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const BIT_DEPTH: u8 = 8;
+const COLOR_TYPE_RGBA: u8 = 6;
+const FILTER_NONE: u8 = 0;
+const DEFLATE_LEVEL: u8 = 6;
+
+// PNG dimensions are capped at 2^31 - 1
+const MAX_DIMENSION: usize = i32::MAX as usize;
+// The raw RGBA pixels are built in memory before they get compressed, 256MB of them at most
+const MAX_PIXEL_BYTES: usize = 1 << 28;
+
+// How export_png draws the field. Each cell becomes a scale x scale block of pixels in the color
+// of its state, the defaults match the canvas renderer: empty cells transparent, agents red
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PngOptions {
+    scale: usize,
+    palette: Palette
+}
+
+impl Default for PngOptions {
+    fn default() -> PngOptions {
+        PngOptions { scale: 1, palette: Palette::default() }
+    }
+}
+
+#[wasm_bindgen]
+impl PngOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> PngOptions {
+        PngOptions::default()
+    }
+
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.max(1);
+    }
+
+    // color of the empty cells
+    pub fn set_background(&mut self, r: u8, g: u8, b: u8, a: u8) {
+        self.palette.empty = [r, g, b, a];
+    }
+
+    // color of free agents
+    pub fn set_occupied_color(&mut self, r: u8, g: u8, b: u8, a: u8) {
+        self.palette.occupied = [r, g, b, a];
+    }
+
+    pub fn set_stuck_color(&mut self, r: u8, g: u8, b: u8, a: u8) {
+        self.palette.stuck = [r, g, b, a];
+    }
+
+    pub fn set_wall_color(&mut self, r: u8, g: u8, b: u8, a: u8) {
+        self.palette.wall = [r, g, b, a];
+    }
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    // the field as an 8 bit RGBA PNG, top row first like on the canvas
+    pub fn export_png(&self, options: &PngOptions) -> Result<Vec<u8>, DlaError> {
+        let scale = options.scale;
+        let image_width = self.width.checked_mul(scale).filter(|width| *width <= MAX_DIMENSION);
+        let image_height = self.height.checked_mul(scale).filter(|height| *height <= MAX_DIMENSION);
+
+        let (image_width, image_height) = match (image_width, image_height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+            _ => return Err(DlaError::InvalidData(format!(
+                "scale: a {}x{} field at scale {} doesn't fit in a PNG", self.width, self.height, scale)))
+        };
+
+        let pixel_bytes = image_width.checked_mul(image_height).and_then(|pixels| pixels.checked_mul(4));
+        if pixel_bytes.is_none_or(|bytes| bytes > MAX_PIXEL_BYTES) {
+            return Err(DlaError::InvalidData(format!(
+                "scale: a {}x{} image is too large, PNG exports are limited to {} bytes of pixels",
+                image_width, image_height, MAX_PIXEL_BYTES)));
+        }
+
+        // every row starts with its filter type
+        let row_len = 1 + image_width * 4;
+        let mut raw = Vec::with_capacity(row_len * image_height);
+        let mut row = vec![0; row_len];
+
+        for y in 0..self.height {
+            row[0] = FILTER_NONE;
+            for x in 0..self.width {
+                let color = options.palette.color_for(self.position_hash.get(x, y).state);
+
+                for pixel in 0..scale {
+                    let offset = 1 + (x * scale + pixel) * 4;
+                    row[offset..offset + 4].copy_from_slice(&color);
+                }
+            }
+
+            for _ in 0..scale {
+                raw.extend_from_slice(&row);
+            }
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(image_width as u32).to_be_bytes());
+        header.extend_from_slice(&(image_height as u32).to_be_bytes());
+        // bit depth, color type, compression, filter and interlace method
+        header.extend_from_slice(&[BIT_DEPTH, COLOR_TYPE_RGBA, 0, 0, 0]);
+
+        let image_data = compress_to_vec_zlib(&raw, DEFLATE_LEVEL);
+
+        let mut png = Vec::with_capacity(PNG_SIGNATURE.len() + image_data.len() + 64);
+        png.extend_from_slice(&PNG_SIGNATURE);
+        push_chunk(&mut png, b"IHDR", &header);
+        push_chunk(&mut png, b"IDAT", &image_data);
+        push_chunk(&mut png, b"IEND", &[]);
+
+        Ok(png)
+    }
+}
+
+// length, type, data and a CRC over type and data, integers are big endian in PNG
+fn push_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
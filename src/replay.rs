@@ -0,0 +1,234 @@
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::bytes::{base64_decode, base64_encode, digest};
+use crate::rng::Rng;
+use crate::error::DlaError;
+
+// A run boiled down to what it takes to regenerate it, written out as JSON
+//
+// {
+//   "version": 2,
+//   "crate_version": "0.1.0",       // version of wasm-rust-dla that recorded it
+//   "config": {                      // how the starting field was generated, see Replay::record
+//     "canvas_id": "dla-display-1",
+//     "num_agents": 3000,
+//     "width": 600,
+//     "height": 400,
+//     "sparse": false,
+//     "seed": 42
+//   },
+//   "start": "RExBQwIA...",          // or the starting field itself, DLAField::to_compressed in
+//                                    // base64, see Replay::record_field
+//   "ticks": 2500,                   // number of next_state calls the run made
+//   "checkpoints": [                 // optional, digest of DLAField::to_bytes after that tick
+//     { "tick": 500, "digest": "9f3c2a6e1b7d4c05" }
+//   ],
+//   "digest": "0a1b2c3d4e5f6789"     // digest of the field after the last tick
+// }
+//
+// A replay holds exactly one of config and start. There is no log of events, the simulation is
+// deterministic for a given field and rng state, so the field is rebuilt and stepped with
+// next_state again and the digests only match as long as that keeps producing the exact same
+// output. Version 1 replays, which always have a config, still load
+pub const REPLAY_VERSION: u32 = 2;
+
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayConfig {
+    canvas_id: String,
+    num_agents: usize,
+    width: usize,
+    height: usize,
+    sparse: bool,
+    seed: u32
+}
+
+#[wasm_bindgen]
+impl ReplayConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas_id: String, num_agents: usize, width: usize, height: usize, sparse: bool, seed: u32) -> ReplayConfig {
+        ReplayConfig { canvas_id, num_agents, width, height, sparse, seed }
+    }
+
+    // the field every replay with this config starts from
+    pub fn build_field(&self) -> Result<DLAField, DlaError> {
+        DLAField::with_random_agents(
+            self.canvas_id.clone(),
+            self.num_agents,
+            self.width,
+            self.height,
+            self.sparse,
+            Rng::new(self.seed as u64)
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Checkpoint {
+    tick: usize,
+    digest: String
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replay {
+    version: u32,
+    crate_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    config: Option<ReplayConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    ticks: usize,
+    #[serde(default)]
+    checkpoints: Vec<Checkpoint>,
+    digest: String
+}
+
+#[wasm_bindgen]
+impl Replay {
+    // Runs the config for up to the given number of ticks, stopping early once every agent is
+    // stuck. A checkpoint_interval of 0 records no checkpoints
+    pub fn record(config: &ReplayConfig, ticks: usize, checkpoint_interval: usize) -> Result<Replay, DlaError> {
+        Replay::record_from(config.build_field()?, Some(config.clone()), None, ticks, checkpoint_interval)
+    }
+
+    // Same as record, but for any field, imported, loaded or made from a bitmap. The field is
+    // stored in the replay as it is now and left alone, the run is recorded on a copy
+    pub fn record_field(field: &DLAField, ticks: usize, checkpoint_interval: usize) -> Result<Replay, DlaError> {
+        // recorded from the stored copy rather than the field itself, so verify starts from
+        // exactly the same place
+        let compressed = field.to_compressed();
+        let start_field = DLAField::from_compressed(&compressed)?;
+
+        Replay::record_from(start_field, None, Some(base64_encode(&compressed)), ticks, checkpoint_interval)
+    }
+
+    pub fn get_crate_version(&self) -> String {
+        self.crate_version.clone()
+    }
+
+    pub fn get_ticks(&self) -> usize {
+        self.ticks
+    }
+
+    // None for replays recorded with record_field
+    pub fn get_config(&self) -> Option<ReplayConfig> {
+        self.config.clone()
+    }
+
+    // regenerates the run up to the given tick
+    pub fn field_at(&self, tick: usize) -> Result<DLAField, DlaError> {
+        if tick > self.ticks {
+            return Err(DlaError::TickOutOfRange { tick, oldest: 0, newest: self.ticks });
+        }
+
+        let mut field = self.start_field()?;
+        for _ in 0..tick {
+            field.next_state();
+        }
+
+        Ok(field)
+    }
+
+    // Regenerates the whole run and checks it against every checkpoint and the final digest,
+    // failing at the first tick where the current code produces something different
+    pub fn verify(&self) -> Result<DLAField, DlaError> {
+        let mut field = self.start_field()?;
+        let mut checkpoints = self.checkpoints.iter().peekable();
+
+        for tick in 1..=self.ticks {
+            field.next_state();
+
+            while let Some(checkpoint) = checkpoints.next_if(|checkpoint| checkpoint.tick <= tick) {
+                let found = field_digest(&field);
+
+                if checkpoint.tick != tick || checkpoint.digest != found {
+                    return Err(DlaError::ReplayMismatch { tick, expected: checkpoint.digest.clone(), found });
+                }
+            }
+        }
+
+        if let Some(checkpoint) = checkpoints.next() {
+            return Err(DlaError::InvalidData(format!(
+                "checkpoints: tick {} is past the end of the run at tick {}", checkpoint.tick, self.ticks)));
+        }
+
+        let found = field_digest(&field);
+        if found != self.digest {
+            return Err(DlaError::ReplayMismatch { tick: self.ticks, expected: self.digest.clone(), found });
+        }
+
+        Ok(field)
+    }
+
+    pub fn to_json(&self) -> Result<String, DlaError> {
+        serde_json::to_string(self).map_err(|err| DlaError::InvalidData(err.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Replay, DlaError> {
+        let replay: Replay = serde_json::from_str(json).map_err(|err| DlaError::InvalidData(err.to_string()))?;
+
+        if replay.version == 0 || replay.version > REPLAY_VERSION {
+            return Err(DlaError::InvalidData(format!(
+                "version: replay version {} is not supported, expected at most {}", replay.version, REPLAY_VERSION)));
+        }
+
+        match (&replay.config, &replay.start) {
+            (Some(_), None) => Ok(replay),
+            (None, Some(_)) if replay.version > 1 => Ok(replay),
+            _ => Err(DlaError::InvalidData("replay: needs either a config or a start field, not both".to_string()))
+        }
+    }
+}
+
+impl Replay {
+    fn record_from(
+        mut field: DLAField,
+        config: Option<ReplayConfig>,
+        start: Option<String>,
+        ticks: usize,
+        checkpoint_interval: usize
+    ) -> Result<Replay, DlaError> {
+        let mut checkpoints = vec![];
+        let mut tick = 0;
+
+        while tick < ticks && field.next_state() {
+            tick += 1;
+
+            if checkpoint_interval > 0 && tick % checkpoint_interval == 0 {
+                checkpoints.push(Checkpoint { tick, digest: field_digest(&field) });
+            }
+        }
+
+        Ok(Replay {
+            version: REPLAY_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            config,
+            start,
+            ticks: tick,
+            checkpoints,
+            digest: field_digest(&field)
+        })
+    }
+
+    // the field the run starts from, rebuilt from the config or read back from start
+    fn start_field(&self) -> Result<DLAField, DlaError> {
+        match (&self.config, &self.start) {
+            (Some(config), _) => config.build_field(),
+            (None, Some(start)) => DLAField::from_bytes(&base64_decode(start, "start")?),
+            (None, None) => Err(DlaError::InvalidData("replay: needs either a config or a start field".to_string()))
+        }
+    }
+}
+
+// Checks that a saved replay still regenerates the exact same run with the current code, returns
+// the final field when it does
+#[wasm_bindgen]
+pub fn verify_replay(json: &str) -> Result<DLAField, DlaError> {
+    Replay::from_json(json)?.verify()
+}
+
+fn field_digest(field: &DLAField) -> String {
+    format!("{:016x}", digest(&field.to_bytes()))
+}
@@ -0,0 +1,61 @@
+// Small seedable generator (SplitMix64) so runs can be reproduced and so the simulation doesn't
+// depend on Math.random, which is only available when running inside a JS host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    // seeded from Math.random in the browser and from the clock on native builds
+    pub fn from_entropy() -> Rng {
+        Rng::new(Rng::entropy_seed())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn entropy_seed() -> u64 {
+        (js_sys::Math::random() * u32::MAX as f64) as u64
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn entropy_seed() -> u64 {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+
+    pub fn get_state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn gen_range(&mut self, min: usize, max: usize) -> usize {
+        let cast_min = min as f64;
+        let cast_max = max as f64;
+
+        (self.next_f64() * (cast_max - cast_min) + cast_min).floor() as usize
+    }
+
+    pub fn gen_bool(&mut self, prob: f64) -> bool {
+        self.next_f64() <= prob
+    }
+}
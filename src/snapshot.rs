@@ -0,0 +1,180 @@
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::bytes::*;
+use crate::colorized_point::{AgentState, Color, ColorizedPoint, StickyNeighbor};
+use crate::compressed::COMPRESSED_MAGIC;
+use crate::rng::Rng;
+use crate::error::DlaError;
+
+// Binary snapshot of a field, every integer is little endian
+//
+//   magic           4 bytes  "DLAF"
+//   version         u16      SNAPSHOT_VERSION
+//   flags           u8       bit 0 sparse storage, bit 1 change tracking, bit 2 walls (version 2)
+//   width, height   u32, u32
+//   canvas_id       u32 byte length followed by utf8
+//   rng state       u64
+//   agent count     u32
+//   agents, each:
+//     x, y          u32, u32
+//     state         u8       0 free, 1 stuck
+//     color         4 x u8   r, g, b, a
+//     has parent    u8       0 or 1
+//     parent x, y   u32, u32 only present when has parent is 1
+//   wall count      u32      only present with the walls flag
+//   walls, each:
+//     x, y          u32, u32
+//
+// Agents are written in the field's own order so restoring gives back an identical field.
+// from_bytes also takes the compressed encoding, see compressed.rs, so either can be saved.
+// Version 2 added walls, version 1 snapshots still load
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"DLAF";
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+const FLAG_SPARSE: u8 = 1;
+const FLAG_TRACK_CHANGES: u8 = 1 << 1;
+const FLAG_WALLS: u8 = 1 << 2;
+
+const STATE_FREE: u8 = 0;
+const STATE_STUCK: u8 = 1;
+
+#[wasm_bindgen]
+impl DLAField {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + self.canvas_id.len() + self.agents.len() * 22);
+
+        bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        push_u16(&mut bytes, SNAPSHOT_VERSION);
+
+        let mut flags = 0;
+        if self.is_sparse() {
+            flags |= FLAG_SPARSE;
+        }
+        if self.track_changes {
+            flags |= FLAG_TRACK_CHANGES;
+        }
+        if self.get_wall_count() > 0 {
+            flags |= FLAG_WALLS;
+        }
+        push_u8(&mut bytes, flags);
+
+        push_u32(&mut bytes, self.width as u32);
+        push_u32(&mut bytes, self.height as u32);
+        push_str(&mut bytes, &self.canvas_id);
+        push_u64(&mut bytes, self.rng.get_state());
+
+        push_u32(&mut bytes, self.agents.len() as u32);
+        for agent in self.agents.iter() {
+            push_u32(&mut bytes, agent.get_x() as u32);
+            push_u32(&mut bytes, agent.get_y() as u32);
+            push_u8(&mut bytes, match agent.state {
+                AgentState::FREE => STATE_FREE,
+                AgentState::STUCK => STATE_STUCK
+            });
+
+            let color = agent.get_color();
+            bytes.extend_from_slice(&[color.get_r(), color.get_g(), color.get_b(), color.get_a()]);
+
+            match agent.sticky_neighbor {
+                None => push_u8(&mut bytes, 0),
+                Some(neighbor) => {
+                    push_u8(&mut bytes, 1);
+                    push_u32(&mut bytes, neighbor.x as u32);
+                    push_u32(&mut bytes, neighbor.y as u32);
+                }
+            }
+        }
+
+        if flags & FLAG_WALLS != 0 {
+            let walls = self.walls();
+            push_u32(&mut bytes, walls.len() as u32);
+            for (x, y) in walls {
+                push_u32(&mut bytes, x as u32);
+                push_u32(&mut bytes, y as u32);
+            }
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<DLAField, DlaError> {
+        if bytes.starts_with(COMPRESSED_MAGIC) {
+            return DLAField::from_compressed(bytes);
+        }
+
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.read_bytes(4, "magic")? != SNAPSHOT_MAGIC {
+            return Err(DlaError::InvalidData("magic: not a DLAField snapshot".to_string()));
+        }
+
+        let version = reader.read_u16("version")?;
+        if version == 0 || version > SNAPSHOT_VERSION {
+            return Err(DlaError::InvalidData(format!(
+                "version: snapshot version {} is not supported, expected at most {}", version, SNAPSHOT_VERSION)));
+        }
+
+        let flags = reader.read_u8("flags")?;
+        if flags & FLAG_WALLS != 0 && version < 2 {
+            return Err(DlaError::InvalidData(format!("flags: snapshot version {} has no walls", version)));
+        }
+        let width = reader.read_u32("width")? as usize;
+        let height = reader.read_u32("height")? as usize;
+        let canvas_id = reader.read_str("canvas_id")?;
+        let rng = Rng::new(reader.read_u64("rng")?);
+
+        let num_agents = reader.read_u32("agents")? as usize;
+        let mut agents = Vec::with_capacity(num_agents.min(bytes.len()));
+
+        for ndx in 0..num_agents {
+            let what = format!("agents[{}]", ndx);
+
+            let x = reader.read_u32(&what)? as usize;
+            let y = reader.read_u32(&what)? as usize;
+            let state = match reader.read_u8(&what)? {
+                STATE_FREE => AgentState::FREE,
+                STATE_STUCK => AgentState::STUCK,
+                other => return Err(DlaError::InvalidData(format!("{}.state: unknown agent state {}", what, other)))
+            };
+
+            let rgba = reader.read_bytes(4, &what)?;
+            let color = Color::new(rgba[0], rgba[1], rgba[2], rgba[3]);
+
+            let sticky_neighbor = match reader.read_u8(&what)? {
+                0 => None,
+                _ => Some(StickyNeighbor::new(
+                    reader.read_u32(&what)? as usize,
+                    reader.read_u32(&what)? as usize
+                ))
+            };
+
+            let mut agent = ColorizedPoint::new(x, y, color, sticky_neighbor);
+            agent.state = state;
+            agents.push(agent);
+        }
+
+        let mut walls = vec![];
+        if flags & FLAG_WALLS != 0 {
+            let num_walls = reader.read_u32("walls")? as usize;
+            for ndx in 0..num_walls {
+                let what = format!("walls[{}]", ndx);
+                walls.push((reader.read_u32(&what)? as usize, reader.read_u32(&what)? as usize));
+            }
+        }
+
+        if !reader.is_empty() {
+            return Err(DlaError::InvalidData("unexpected data after the last agent or wall".to_string()));
+        }
+
+        let mut field = DLAField::from_agents(
+            canvas_id, width, height, flags & FLAG_SPARSE != 0, agents, rng)?;
+        field.add_walls(&walls)?;
+
+        if flags & FLAG_TRACK_CHANGES != 0 {
+            field.set_track_changes(true);
+        }
+
+        Ok(field)
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{DLAField, MAX_DENSE_CELLS};
+use crate::colorized_point::ColorizedPoint;
+use crate::error::DlaError;
+
+const HEADER_LEN: usize = 80;
+const TRIANGLE_LEN: usize = 50;
+// width of the strips bridging columns that only touch diagonally, in cells
+const BRIDGE_WIDTH: f64 = 1.0 / 32.0;
+
+// Settings for export_stl, lengths in millimeters. Every stuck cell becomes a cell_size x
+// cell_size column standing on z = 0, base_height + height_per_depth * depth tall where depth is
+// the hops to its root. A height_per_depth of 0 extrudes the whole aggregate evenly, a negative
+// one with a big enough base makes the roots the peaks
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StlOptions {
+    cell_size: f64,
+    base_height: f64,
+    height_per_depth: f64
+}
+
+impl Default for StlOptions {
+    fn default() -> StlOptions {
+        StlOptions {
+            cell_size: 1.0,
+            base_height: 1.0,
+            height_per_depth: 0.0
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl StlOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> StlOptions {
+        StlOptions::default()
+    }
+
+    pub fn set_cell_size(&mut self, cell_size: f64) {
+        self.cell_size = cell_size;
+    }
+
+    pub fn set_base_height(&mut self, base_height: f64) {
+        self.base_height = base_height;
+    }
+
+    pub fn set_height_per_depth(&mut self, height_per_depth: f64) {
+        self.height_per_depth = height_per_depth;
+    }
+}
+
+// An axis aligned rectangle of the mesh on the lattice of cell corners and height levels. The
+// normal points along axis, a and b are the next two axes in x, y, z order wrapping around, so
+// a counter clockwise walk in a and b faces along the positive normal
+#[derive(Clone, Copy, Debug)]
+struct Face {
+    axis: usize,
+    at: usize,
+    positive: bool,
+    min: [usize; 2],
+    max: [usize; 2]
+}
+
+impl Face {
+    fn corner(&self, a: usize, b: usize) -> [usize; 3] {
+        let mut corner = [0; 3];
+        corner[self.axis] = self.at;
+        corner[(self.axis + 1) % 3] = a;
+        corner[(self.axis + 2) % 3] = b;
+        corner
+    }
+
+    // counter clockwise seen from outside
+    fn corners(&self) -> [[usize; 3]; 4] {
+        let (min, max) = (self.min, self.max);
+
+        if self.positive {
+            [self.corner(min[0], min[1]), self.corner(max[0], min[1]), self.corner(max[0], max[1]), self.corner(min[0], max[1])]
+        } else {
+            [self.corner(min[0], min[1]), self.corner(min[0], max[1]), self.corner(max[0], max[1]), self.corner(max[0], min[1])]
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    // The stuck cells as a binary STL, see StlOptions
+    pub fn export_stl(&self, options: &StlOptions) -> Result<Vec<u8>, DlaError> {
+        self.export_stl_with(options.cell_size, |_, depth| options.base_height + options.height_per_depth * depth as f64)
+    }
+}
+
+impl DLAField {
+    // export_stl with the column heights up to the caller, who gets each stuck agent and its hops
+    // to the root, e.g. |agent, _| field.get_distance_from_root(*agent).unwrap() as f64.
+    //
+    // The mesh is closed with every face pointing out. Columns of the same height are merged into
+    // rectangles, top, bottom and walls, and where a rectangle edge passes a corner of a
+    // neighboring one the corner is added to it, so edges always meet edge to edge and there are
+    // no cracks for a slicer to trip over. Cells that only touch at a corner are bridged, so
+    // every edge belongs to exactly two triangles
+    pub fn export_stl_with<F>(&self, cell_size: f64, height_of: F) -> Result<Vec<u8>, DlaError>
+        where F: Fn(&ColorizedPoint, usize) -> f64
+    {
+        if cell_size <= 0.0 || !cell_size.is_finite() {
+            return Err(DlaError::InvalidData(format!("cell_size: {} is not a positive length", cell_size)));
+        }
+
+        let tree = self.aggregate_tree()?;
+
+        // y up like in the slicer, row 0 is the bottom row of the field. Heights are checked as
+        // they will be written, a double that is positive can still come out as 0 or infinity
+        let mut column_heights = Vec::with_capacity(tree.nodes.len());
+        for node in tree.nodes.iter() {
+            let agent = self.position_hash.get(node.x, node.y).agent
+                .ok_or(DlaError::MissingNeighbor { x: node.x, y: node.y })?;
+            let column_height = height_of(&agent, node.depth);
+
+            if column_height as f32 <= 0.0 || !(column_height as f32).is_finite() {
+                return Err(DlaError::InvalidData(format!(
+                    "height: the column at ({}, {}) would be {} tall", node.x, node.y, column_height)));
+            }
+
+            column_heights.push((node.x, self.height - 1 - node.y, column_height as f32));
+        }
+
+        // The grids below only cover the box around the stuck cells, a sparse field can be far
+        // larger than anything worth allocating cell by cell. The box is capped like a dense field
+        let origin_x = column_heights.iter().map(|&(x, _, _)| x).min().unwrap_or(0);
+        let origin_y = column_heights.iter().map(|&(_, y, _)| y).min().unwrap_or(0);
+        let width = column_heights.iter().map(|&(x, _, _)| x + 1 - origin_x).max().unwrap_or(0);
+        let height = column_heights.iter().map(|&(_, y, _)| y + 1 - origin_y).max().unwrap_or(0);
+        if width.checked_mul(height).is_none_or(|cells| cells > MAX_DENSE_CELLS) {
+            return Err(DlaError::InvalidData(format!(
+                "stl: the stuck cells span {}x{} cells, more than the {} a mesh is built over",
+                width, height, MAX_DENSE_CELLS)));
+        }
+        for column in column_heights.iter_mut() {
+            column.0 -= origin_x;
+            column.1 -= origin_y;
+        }
+
+        // distinct heights, level 0 is the ground and empty cells. Deduplicated in single
+        // precision, heights only apart as doubles would make walls of no height
+        let mut heights = vec![0.0];
+        heights.extend(column_heights.iter().map(|&(_, _, column_height)| column_height));
+        heights.sort_by(f32::total_cmp);
+        heights.dedup();
+
+        let mut cell_levels = vec![0; width * height];
+        for &(x, y, column_height) in column_heights.iter() {
+            cell_levels[y * width + x] = heights.binary_search_by(|level| level.total_cmp(&column_height))
+                .unwrap_or_else(|level| level);
+        }
+        let cell_level = |x: usize, y: usize| cell_levels[y * width + x];
+
+        // Columns that only touch diagonally would share a vertical edge between four walls, which
+        // isn't manifold and trips up slicers. Every lattice line through such an edge gets a strip
+        // of its own, see refine_axis, standing as tall as the tallest cell it borders. That
+        // bridges the two columns and moves the walls along the line out by half a strip
+        let is_pinched = |x: usize, y: usize| {
+            let (a, b) = (cell_level(x - 1, y - 1), cell_level(x, y));
+            let (c, d) = (cell_level(x, y - 1), cell_level(x - 1, y));
+            a.min(b) > c.max(d) || c.min(d) > a.max(b)
+        };
+        let mut pinched_x = vec![false; width + 1];
+        let mut pinched_y = vec![false; height + 1];
+        for (x, y) in (1..height).flat_map(|y| (1..width).map(move |x| (x, y))).filter(|&(x, y)| is_pinched(x, y)) {
+            pinched_x[x] = true;
+            pinched_y[y] = true;
+        }
+
+        let (spans_x, lines_x) = refine_axis(width, &pinched_x);
+        let (spans_y, lines_y) = refine_axis(height, &pinched_y);
+        let lines_x = single_precision(&lines_x, origin_x, cell_size)?;
+        let lines_y = single_precision(&lines_y, origin_y, cell_size)?;
+
+        // the rest works on the refined grid
+        let (width, height) = (spans_x.len(), spans_y.len());
+        let mut levels = Vec::with_capacity(width * height);
+        for span_y in spans_y.iter() {
+            for span_x in spans_x.iter() {
+                let level = span_y.clone()
+                    .flat_map(|y| span_x.clone().map(move |x| (x, y)))
+                    .map(|(x, y)| cell_level(x, y))
+                    .max()
+                    .unwrap_or(0);
+                levels.push(level);
+            }
+        }
+        let level = |x: usize, y: usize| levels[y * width + x];
+
+        let mut faces = vec![];
+
+        for (min, max, top) in merge_cells(width, height, |x, y| Some(level(x, y)).filter(|&top| top > 0)) {
+            faces.push(Face { axis: 2, at: top, positive: true, min, max });
+        }
+        for (min, max, _) in merge_cells(width, height, |x, y| Some(0).filter(|_| level(x, y) > 0)) {
+            faces.push(Face { axis: 2, at: 0, positive: false, min, max });
+        }
+
+        // walls where a column stands above its neighbor, runs of the same span become one face.
+        // Walls facing along x are spanned by y and z, walls facing along y by z and x
+        for x in 0..=width {
+            let mut walls = vec![];
+            for y in 0..height {
+                let left = if x > 0 { level(x - 1, y) } else { 0 };
+                let right = if x < width { level(x, y) } else { 0 };
+
+                if left != right {
+                    walls.push((y, left > right, left.min(right), left.max(right)));
+                }
+            }
+
+            for (start, end, positive, bottom, top) in merge_walls(walls) {
+                faces.push(Face { axis: 0, at: x, positive, min: [start, bottom], max: [end, top] });
+            }
+        }
+
+        for y in 0..=height {
+            let mut walls = vec![];
+            for x in 0..width {
+                let below = if y > 0 { level(x, y - 1) } else { 0 };
+                let above = if y < height { level(x, y) } else { 0 };
+
+                if below != above {
+                    walls.push((x, below > above, below.min(above), below.max(above)));
+                }
+            }
+
+            for (start, end, positive, bottom, top) in merge_walls(walls) {
+                faces.push(Face { axis: 1, at: y, positive, min: [bottom, start], max: [top, end] });
+            }
+        }
+
+        // every corner on each line through it, to find the ones lying on another face's edge
+        let mut lines: HashMap<(usize, [usize; 3]), Vec<usize>> = HashMap::new();
+        for face in faces.iter() {
+            for corner in face.corners().iter() {
+                for axis in 0..3 {
+                    let mut key = *corner;
+                    key[axis] = 0;
+                    lines.entry((axis, key)).or_default().push(corner[axis]);
+                }
+            }
+        }
+        for positions in lines.values_mut() {
+            positions.sort_unstable();
+            positions.dedup();
+        }
+
+        let position = |point: [usize; 3]| [lines_x[point[0]], lines_y[point[1]], heights[point[2]]];
+
+        let mut triangles = vec![];
+        for face in faces.iter() {
+            let corners = face.corners();
+            let mut outline = vec![];
+
+            for (ndx, &from) in corners.iter().enumerate() {
+                let to = corners[(ndx + 1) % 4];
+                let axis = (0..3).find(|&axis| from[axis] != to[axis]).unwrap();
+                let mut key = from;
+                key[axis] = 0;
+
+                outline.push(from);
+                let mut between: Vec<usize> = lines[&(axis, key)].iter()
+                    .copied()
+                    .filter(|&at| at > from[axis].min(to[axis]) && at < from[axis].max(to[axis]))
+                    .collect();
+                if from[axis] > to[axis] {
+                    between.reverse();
+                }
+
+                for at in between {
+                    let mut point = from;
+                    point[axis] = at;
+                    outline.push(point);
+                }
+            }
+
+            let mut normal = [0.0; 3];
+            normal[face.axis] = if face.positive { 1.0 } else { -1.0 };
+            let outline: Vec<[f32; 3]> = outline.into_iter().map(position).collect();
+
+            if outline.len() == 4 {
+                triangles.push((normal, [outline[0], outline[1], outline[2]]));
+                triangles.push((normal, [outline[0], outline[2], outline[3]]));
+            } else {
+                // a fan around the middle, a fan from a corner would have flat triangles along the
+                // split edges
+                let (low, high) = (position(corners[0]), position(corners[2]));
+                let center = [(low[0] + high[0]) / 2.0, (low[1] + high[1]) / 2.0, (low[2] + high[2]) / 2.0];
+
+                for ndx in 0..outline.len() {
+                    triangles.push((normal, [center, outline[ndx], outline[(ndx + 1) % outline.len()]]));
+                }
+            }
+        }
+
+        let mut stl = Vec::with_capacity(HEADER_LEN + 4 + triangles.len() * TRIANGLE_LEN);
+        let mut header = [0; HEADER_LEN];
+        let title = b"wasm-rust-dla";
+        header[..title.len()].copy_from_slice(title);
+        stl.extend_from_slice(&header);
+        stl.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+
+        for (normal, vertices) in triangles.iter() {
+            for value in normal.iter().chain(vertices.iter().flatten()) {
+                stl.extend_from_slice(&value.to_le_bytes());
+            }
+            // attribute byte count, unused
+            stl.extend_from_slice(&[0, 0]);
+        }
+
+        Ok(stl)
+    }
+}
+
+// Splits an axis of the field into the spans of the refined grid, one per cell plus a strip
+// BRIDGE_WIDTH across on every pinched lattice line. A strip takes half its width from the cells
+// on either side. Returns the cells each span covers and where the lines between spans lie, in
+// cells from the start of the axis
+fn refine_axis(cells: usize, pinched: &[bool]) -> (Vec<Range<usize>>, Vec<f64>) {
+    let mut spans = vec![];
+    let mut lines = vec![];
+
+    for (line, &pinched) in pinched.iter().enumerate() {
+        if pinched {
+            lines.push(line as f64 - BRIDGE_WIDTH / 2.0);
+            spans.push(line.saturating_sub(1)..(line + 1).min(cells));
+            lines.push(line as f64 + BRIDGE_WIDTH / 2.0);
+        } else {
+            lines.push(line as f64);
+        }
+
+        if line < cells {
+            spans.push(line..line + 1);
+        }
+    }
+
+    (spans, lines)
+}
+
+// the lattice lines of the box at origin in millimeters, as they will be written they have to stay
+// apart
+fn single_precision(lines: &[f64], origin: usize, cell_size: f64) -> Result<Vec<f32>, DlaError> {
+    let lines: Vec<f32> = lines.iter().map(|&line| ((origin as f64 + line) * cell_size) as f32).collect();
+
+    if lines.windows(2).any(|pair| pair[0] >= pair[1]) || lines.iter().any(|line| !line.is_finite()) {
+        return Err(DlaError::InvalidData(format!(
+            "cell_size: {} mm cells can't be told apart in single precision", cell_size)));
+    }
+
+    Ok(lines)
+}
+
+// Greedy rectangles over the cells with the same key, each grown along x as far as it goes and
+// then along y while whole rows match. Returns the lattice corners and the key
+fn merge_cells<K, F>(width: usize, height: usize, key: F) -> Vec<([usize; 2], [usize; 2], K)>
+    where K: PartialEq + Copy, F: Fn(usize, usize) -> Option<K>
+{
+    let mut taken = vec![false; width * height];
+    let mut rectangles = vec![];
+
+    for y in 0..height {
+        for x in 0..width {
+            let value = match key(x, y) {
+                Some(value) if !taken[y * width + x] => value,
+                _ => continue
+            };
+            let matches = |x: usize, y: usize| !taken[y * width + x] && key(x, y) == Some(value);
+
+            let mut end_x = x + 1;
+            while end_x < width && matches(end_x, y) {
+                end_x += 1;
+            }
+
+            let mut end_y = y + 1;
+            while end_y < height && (x..end_x).all(|x| matches(x, end_y)) {
+                end_y += 1;
+            }
+
+            for row in y..end_y {
+                for column in x..end_x {
+                    taken[row * width + column] = true;
+                }
+            }
+
+            rectangles.push(([x, y], [end_x, end_y], value));
+        }
+    }
+
+    rectangles
+}
+
+// Joins the walls along one line, (cell, positive, bottom level, top level) in cell order, into
+// runs of (start, end, positive, bottom, top)
+fn merge_walls(walls: Vec<(usize, bool, usize, usize)>) -> Vec<(usize, usize, bool, usize, usize)> {
+    let mut runs: Vec<(usize, usize, bool, usize, usize)> = vec![];
+
+    for (cell, positive, bottom, top) in walls {
+        match runs.last_mut() {
+            Some(run) if run.1 == cell && run.2 == positive && run.3 == bottom && run.4 == top => run.1 = cell + 1,
+            _ => runs.push((cell, cell + 1, positive, bottom, top))
+        }
+    }
+
+    runs
+}
@@ -0,0 +1,176 @@
+use std::fmt::Write;
+
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::tree::AggregateTree;
+use crate::error::DlaError;
+use crate::utils::format_decimal;
+
+// How export_svg draws the aggregate. Every stuck agent is a dot and a line to its sticky
+// neighbor, coordinates are cell centers times scale with y pointing down like on the canvas
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SvgOptions {
+    scale: f64,
+    stroke_width: f64,
+    // stroke_width times the square root of the agents hanging off a link, so trunks come out
+    // thicker than twigs
+    stroke_by_subtree: bool,
+    node_radius: f64,
+    // colors are blended from the roots to the deepest agents
+    root_color: [u8; 3],
+    tip_color: [u8; 3],
+    background: Option<[u8; 3]>,
+    // draw runs of links without branches as one path, far fewer elements for plotters and
+    // engravers but the whole run takes the style of its first link
+    merge_paths: bool
+}
+
+impl Default for SvgOptions {
+    fn default() -> SvgOptions {
+        SvgOptions {
+            scale: 1.0,
+            stroke_width: 0.5,
+            stroke_by_subtree: false,
+            node_radius: 0.5,
+            root_color: [255, 0, 0],
+            tip_color: [255, 0, 0],
+            background: None,
+            merge_paths: false
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl SvgOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> SvgOptions {
+        SvgOptions::default()
+    }
+
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale;
+    }
+
+    pub fn set_stroke_width(&mut self, stroke_width: f64) {
+        self.stroke_width = stroke_width;
+    }
+
+    pub fn set_stroke_by_subtree(&mut self, stroke_by_subtree: bool) {
+        self.stroke_by_subtree = stroke_by_subtree;
+    }
+
+    // 0 leaves the dots out
+    pub fn set_node_radius(&mut self, node_radius: f64) {
+        self.node_radius = node_radius;
+    }
+
+    pub fn set_depth_colors(&mut self, root_r: u8, root_g: u8, root_b: u8, tip_r: u8, tip_g: u8, tip_b: u8) {
+        self.root_color = [root_r, root_g, root_b];
+        self.tip_color = [tip_r, tip_g, tip_b];
+    }
+
+    pub fn set_background(&mut self, r: u8, g: u8, b: u8) {
+        self.background = Some([r, g, b]);
+    }
+
+    pub fn set_merge_paths(&mut self, merge_paths: bool) {
+        self.merge_paths = merge_paths;
+    }
+}
+
+#[wasm_bindgen]
+impl DLAField {
+    pub fn export_svg(&self, options: &SvgOptions) -> Result<String, DlaError> {
+        let tree = self.aggregate_tree()?;
+        let max_depth = tree.max_depth();
+
+        let width = self.width as f64 * options.scale;
+        let height = self.height as f64 * options.scale;
+        let center = |ndx: usize| {
+            let node = tree.nodes[ndx];
+            ((node.x as f64 + 0.5) * options.scale, (node.y as f64 + 0.5) * options.scale)
+        };
+        let color = |ndx: usize| depth_color(options, tree.nodes[ndx].depth, max_depth);
+
+        let mut svg = String::new();
+        // writing to a String can't fail
+        let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+            format_decimal(width, 3), format_decimal(height, 3), format_decimal(width, 3), format_decimal(height, 3));
+
+        if let Some(background) = options.background {
+            let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="{}"/>"#, rgb(background));
+        }
+
+        let _ = writeln!(svg, r#"<g fill="none" stroke-linecap="round" stroke-linejoin="round">"#);
+
+        // a link is styled after the agent that stuck, its subtree is what the link carries
+        if options.merge_paths {
+            for chain in tree.chains() {
+                let (x, y) = center(chain[0]);
+                let mut path = format!("M{} {}", format_decimal(x, 3), format_decimal(y, 3));
+                for &ndx in chain[1..].iter() {
+                    let (x, y) = center(ndx);
+                    let _ = write!(path, " L{} {}", format_decimal(x, 3), format_decimal(y, 3));
+                }
+
+                let _ = writeln!(svg, r#"<path d="{}" stroke="{}" stroke-width="{}"/>"#,
+                    path, rgb(color(chain[1])), format_decimal(stroke_width(options, &tree, chain[1]), 3));
+            }
+        } else {
+            for (ndx, node) in tree.nodes.iter().enumerate() {
+                if let Some(parent) = node.parent {
+                    let (x1, y1) = center(parent);
+                    let (x2, y2) = center(ndx);
+
+                    let _ = writeln!(svg, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}"/>"#,
+                        format_decimal(x1, 3), format_decimal(y1, 3), format_decimal(x2, 3), format_decimal(y2, 3), rgb(color(ndx)), format_decimal(stroke_width(options, &tree, ndx), 3));
+                }
+            }
+        }
+
+        let _ = writeln!(svg, "</g>");
+
+        if options.node_radius > 0.0 {
+            let _ = writeln!(svg, "<g>");
+            for ndx in 0..tree.nodes.len() {
+                let (x, y) = center(ndx);
+                let _ = writeln!(svg, r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+                    format_decimal(x, 3), format_decimal(y, 3), format_decimal(options.node_radius * options.scale, 3), rgb(color(ndx)));
+            }
+            let _ = writeln!(svg, "</g>");
+        }
+
+        svg.push_str("</svg>\n");
+
+        Ok(svg)
+    }
+}
+
+fn stroke_width(options: &SvgOptions, tree: &AggregateTree, ndx: usize) -> f64 {
+    let width = options.stroke_width * options.scale;
+
+    if options.stroke_by_subtree {
+        width * (tree.nodes[ndx].subtree_size as f64).sqrt()
+    } else {
+        width
+    }
+}
+
+fn depth_color(options: &SvgOptions, depth: usize, max_depth: usize) -> [u8; 3] {
+    let t = if max_depth == 0 { 0.0 } else { depth as f64 / max_depth as f64 };
+    let mut color = [0; 3];
+
+    for (channel, value) in color.iter_mut().enumerate() {
+        let root = options.root_color[channel] as f64;
+        let tip = options.tip_color[channel] as f64;
+        *value = (root + (tip - root) * t).round() as u8;
+    }
+
+    color
+}
+
+fn rgb(color: [u8; 3]) -> String {
+    format!("rgb({},{},{})", color[0], color[1], color[2])
+}
@@ -0,0 +1,182 @@
+use crate::DLAField;
+use crate::colorized_point::{AgentState, Color};
+use crate::error::DlaError;
+
+const NO_PARENT: usize = usize::MAX;
+
+// One stuck agent of the aggregate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeNode {
+    pub x: usize,
+    pub y: usize,
+    pub color: Color,
+    // index into AggregateTree::nodes, None for roots
+    pub parent: Option<usize>,
+    // hops to the root, 0 for roots
+    pub depth: usize,
+    // agents in the subtree hanging off this node, itself included
+    pub subtree_size: usize
+}
+
+// The stuck agents linked up through their sticky neighbors, the shape the exports work from.
+// Nodes are in field order and so are the children of each node
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AggregateTree {
+    pub nodes: Vec<TreeNode>,
+    pub children: Vec<Vec<usize>>,
+    pub roots: Vec<usize>
+}
+
+impl AggregateTree {
+    pub fn max_depth(&self) -> usize {
+        self.nodes.iter().map(|node| node.depth).max().unwrap_or(0)
+    }
+
+    // Splits the tree into chains of links that can be drawn as one stroke. A chain starts at a
+    // root or at a node that branches, then follows the only child for as long as there is
+    // exactly one. Chains are lists of node indices, parent first
+    pub fn chains(&self) -> Vec<Vec<usize>> {
+        let mut chains = vec![];
+
+        for (ndx, children) in self.children.iter().enumerate() {
+            // a node with a parent and a single child is the middle of someone else's chain
+            if self.nodes[ndx].parent.is_some() && children.len() == 1 {
+                continue;
+            }
+
+            for &child in children.iter() {
+                let mut chain = vec![ndx, child];
+                let mut current = child;
+
+                while self.children[current].len() == 1 {
+                    current = self.children[current][0];
+                    chain.push(current);
+                }
+
+                chains.push(chain);
+            }
+        }
+
+        chains
+    }
+
+    // Splits the tree into as few continuous strokes as possible, one per tip. Each stroke follows
+    // the child with the biggest subtree, the other children start strokes of their own at the
+    // fork so everything stays connected. Lone roots are strokes of a single node
+    pub fn strokes(&self) -> Vec<Vec<usize>> {
+        let mut strokes = vec![];
+        let mut starts: Vec<(Option<usize>, usize)> = self.roots.iter().map(|&root| (None, root)).collect();
+
+        while let Some((fork, first)) = starts.pop() {
+            let mut stroke: Vec<usize> = fork.into_iter().collect();
+            let mut current = first;
+
+            loop {
+                stroke.push(current);
+
+                let heaviest = self.children[current].iter()
+                    .copied()
+                    .max_by_key(|&child| (self.nodes[child].subtree_size, std::cmp::Reverse(child)));
+
+                match heaviest {
+                    None => break,
+                    Some(heaviest) => {
+                        for &child in self.children[current].iter().rev() {
+                            if child != heaviest {
+                                starts.push((Some(current), child));
+                            }
+                        }
+                        current = heaviest;
+                    }
+                }
+            }
+
+            strokes.push(stroke);
+        }
+
+        strokes
+    }
+}
+
+impl DLAField {
+    // Fails on sticky neighbors that aren't stuck agents and on loops, see validate
+    pub fn aggregate_tree(&self) -> Result<AggregateTree, DlaError> {
+        let stuck: Vec<usize> = (0..self.agents.len())
+            .filter(|&ndx| self.agents[ndx].state == AgentState::STUCK)
+            .collect();
+
+        // field agent index to node index
+        let mut node_of_agent = vec![NO_PARENT; self.agents.len()];
+        for (node, &agent) in stuck.iter().enumerate() {
+            node_of_agent[agent] = node;
+        }
+
+        let mut nodes = Vec::with_capacity(stuck.len());
+        for &agent_ndx in stuck.iter() {
+            let agent = self.agents[agent_ndx];
+
+            let parent = match agent.sticky_neighbor {
+                None => None,
+                Some(neighbor) => {
+                    let missing = DlaError::MissingNeighbor { x: neighbor.x, y: neighbor.y };
+
+                    if neighbor.x >= self.width || neighbor.y >= self.height {
+                        return Err(missing);
+                    }
+
+                    match self.agent_position_lookup.get(neighbor.x, neighbor.y) {
+                        Some(parent_ndx) if node_of_agent[parent_ndx] != NO_PARENT => Some(node_of_agent[parent_ndx]),
+                        _ => return Err(missing)
+                    }
+                }
+            };
+
+            nodes.push(TreeNode {
+                x: agent.get_x(),
+                y: agent.get_y(),
+                color: agent.get_color(),
+                parent,
+                depth: 0,
+                subtree_size: 1
+            });
+        }
+
+        let mut children = vec![vec![]; nodes.len()];
+        let mut roots = vec![];
+        for (ndx, node) in nodes.iter().enumerate() {
+            match node.parent {
+                None => roots.push(ndx),
+                Some(parent) => children[parent].push(ndx)
+            }
+        }
+
+        // walking down from the roots only reaches nodes that aren't part of a loop
+        let mut order = Vec::with_capacity(nodes.len());
+        let mut stack = roots.clone();
+        while let Some(ndx) = stack.pop() {
+            order.push(ndx);
+
+            for &child in children[ndx].iter() {
+                nodes[child].depth = nodes[ndx].depth + 1;
+                stack.push(child);
+            }
+        }
+
+        if order.len() != nodes.len() {
+            let mut reached = vec![false; nodes.len()];
+            order.iter().for_each(|&ndx| reached[ndx] = true);
+
+            let looped = &nodes[reached.iter().position(|reached| !reached).unwrap()];
+            return Err(DlaError::NeighborCycle { x: looped.x, y: looped.y });
+        }
+
+        // children come after their parents in the walk, so going backwards sums up subtrees
+        for &ndx in order.iter().rev() {
+            if let Some(parent) = nodes[ndx].parent {
+                nodes[parent].subtree_size += nodes[ndx].subtree_size;
+            }
+        }
+
+        Ok(AggregateTree { nodes, children, roots })
+    }
+}
@@ -0,0 +1,127 @@
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::colorized_point::AgentState;
+use crate::field_position::FieldState;
+use crate::error::DlaError;
+
+#[wasm_bindgen]
+impl DLAField {
+    // Cross checks the agents and walls against the lookup table, the position hash and the
+    // occupancy bits, which all describe the same cells, and makes sure every sticky neighbor is
+    // a stuck agent. Meant for tests and debugging, it visits every agent and every allocated cell
+    pub fn validate(&self) -> Result<(), DlaError> {
+        let mut violations = vec![];
+        let mut num_stuck = 0;
+
+        for (ndx, agent) in self.agents.iter().enumerate() {
+            let x = agent.get_x();
+            let y = agent.get_y();
+
+            if x >= self.width || y >= self.height {
+                violations.push(format!("agents[{}]: ({}, {}) is outside of the field", ndx, x, y));
+                continue;
+            }
+
+            if self.agent_position_lookup.get(x, y) != Some(ndx) {
+                violations.push(format!(
+                    "agents[{}]: lookup at ({}, {}) has {:?}", ndx, x, y, self.agent_position_lookup.get(x, y)));
+            }
+
+            let expected_state = match agent.state {
+                AgentState::FREE => FieldState::OCCUPIED,
+                AgentState::STUCK => FieldState::STUCK
+            };
+
+            let position = self.position_hash.get(x, y);
+            if position.state != expected_state {
+                violations.push(format!(
+                    "agents[{}]: position hash at ({}, {}) is {:?}, expected {:?}",
+                    ndx, x, y, position.state, expected_state));
+            } else if position.agent != Some(*agent) {
+                violations.push(format!(
+                    "agents[{}]: position hash at ({}, {}) holds a different agent", ndx, x, y));
+            }
+
+            if !self.occupancy.is_occupied(x, y) {
+                violations.push(format!("agents[{}]: occupancy bit at ({}, {}) is not set", ndx, x, y));
+            }
+
+            if self.occupancy.is_wall(x, y) {
+                violations.push(format!("agents[{}]: ({}, {}) is a wall", ndx, x, y));
+            }
+
+            let stuck = match agent.state {
+                AgentState::FREE => false,
+                AgentState::STUCK => true
+            };
+
+            if stuck {
+                num_stuck += 1;
+            }
+
+            if self.occupancy.is_stuck(x, y) != stuck {
+                violations.push(format!(
+                    "agents[{}]: stuck bit at ({}, {}) is {}, expected {}",
+                    ndx, x, y, self.occupancy.is_stuck(x, y), stuck));
+            }
+
+            if let Some(neighbor) = agent.sticky_neighbor {
+                if !stuck {
+                    violations.push(format!("agents[{}]: free agent has a sticky neighbor", ndx));
+                }
+
+                match self.get_agent_at_coordinate(neighbor.x, neighbor.y) {
+                    None => violations.push(format!(
+                        "agents[{}]: sticky neighbor ({}, {}) has no agent", ndx, neighbor.x, neighbor.y)),
+                    Some(neighbor_agent) => {
+                        if let AgentState::FREE = neighbor_agent.state {
+                            violations.push(format!(
+                                "agents[{}]: sticky neighbor ({}, {}) is not stuck", ndx, neighbor.x, neighbor.y));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut num_walls = 0;
+        for (x, y, position) in self.position_hash.cells().filter(|(_, _, position)| position.state == FieldState::WALL) {
+            num_walls += 1;
+
+            if !self.occupancy.is_wall(x, y) {
+                violations.push(format!("wall at ({}, {}): wall bit is not set", x, y));
+            }
+            if position.agent.is_some() {
+                violations.push(format!("wall at ({}, {}): position hash holds an agent", x, y));
+            }
+        }
+
+        // the per agent checks above can't see cells that are filled in without an agent
+        let num_positions = self.position_hash.cells()
+            .filter(|(_, _, position)| position.state != FieldState::EMPTY && position.state != FieldState::WALL)
+            .count();
+        let num_lookups = self.agent_position_lookup.cells()
+            .filter(|(_, _, agent_ndx)| agent_ndx.is_some())
+            .count();
+
+        let counts = [
+            ("position hash", num_positions, self.agents.len()),
+            ("lookup", num_lookups, self.agents.len()),
+            ("occupancy", self.occupancy.occupied_count(), self.agents.len()),
+            ("stuck occupancy", self.occupancy.stuck_count(), num_stuck),
+            ("wall occupancy", self.occupancy.wall_count(), num_walls)
+        ];
+
+        for (name, found, expected) in counts.iter() {
+            if found != expected {
+                violations.push(format!("{} has {} filled cells, expected {}", name, found, expected));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(DlaError::Inconsistent(violations))
+        }
+    }
+}
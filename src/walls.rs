@@ -0,0 +1,58 @@
+use wasm_bindgen::prelude::*;
+
+use crate::DLAField;
+use crate::field_position::{FieldPosition, FieldState};
+use crate::error::DlaError;
+
+// Walls are cells agents can neither move into nor stick to. They are part of the field's setup,
+// see from_bitmap, and stay put from then on
+#[wasm_bindgen]
+impl DLAField {
+    pub fn get_wall_count(&self) -> usize {
+        self.occupancy.wall_count()
+    }
+
+    pub fn is_wall(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.occupancy.is_wall(x, y)
+    }
+}
+
+impl DLAField {
+    // column by column, top row first
+    pub fn walls(&self) -> Vec<(usize, usize)> {
+        let mut walls: Vec<(usize, usize)> = self.position_hash.cells()
+            .filter(|(_, _, position)| position.state == FieldState::WALL)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        walls.sort_unstable();
+
+        walls
+    }
+
+    // Fails on walls off the field or on top of an agent, walls listed twice are fine. Every wall
+    // is checked before any goes in, so on failure the field is left as it was
+    pub fn add_walls(&mut self, walls: &[(usize, usize)]) -> Result<(), DlaError> {
+        for (ndx, &(x, y)) in walls.iter().enumerate() {
+            if x >= self.width || y >= self.height {
+                return Err(DlaError::InvalidData(format!(
+                    "walls[{}]: ({}, {}) is outside of the {}x{} field", ndx, x, y, self.width, self.height)));
+            }
+
+            if let Some(agent_ndx) = self.agent_position_lookup.get(x, y) {
+                return Err(DlaError::InvalidData(format!(
+                    "walls[{}]: ({}, {}) is already taken by agents[{}]", ndx, x, y, agent_ndx)));
+            }
+        }
+
+        for &(x, y) in walls.iter() {
+            self.position_hash.set(x, y, FieldPosition::new(FieldState::WALL, None));
+            self.occupancy.set_wall(x, y, true);
+
+            if self.track_changes {
+                self.changes.record_wall(x, y);
+            }
+        }
+
+        Ok(())
+    }
+}
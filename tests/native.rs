@@ -0,0 +1,1700 @@
+//! Test suite for native builds.
+
+#![cfg(not(target_arch = "wasm32"))]
+#![allow(non_snake_case)]
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use wasm_rust_dla::{
+    AgentState, ArrivalTimes, Autosave, AutosaveSink, BitmapCell, BitmapLegend, ChangeSet, ColorizedPoint, DLAField, DlaError, DxfOptions, GcodeOptions, History, PngOptions, Replay, ReplayConfig, StlOptions, SvgOptions, latest_autosave, verify_replay
+};
+
+#[test]
+fn next_state_parallel_shouldNotDependOnThreadCount() {
+    let mut single = DLAField::new_seeded("test".to_string(), 2000, 100, 100, 7).unwrap();
+    let mut many = DLAField::new_seeded("test".to_string(), 2000, 100, 100, 7).unwrap();
+
+    for _ in 0..200 {
+        single.next_state_parallel(1);
+        many.next_state_parallel(8);
+    }
+
+    assert_eq!(single, many);
+    assert_eq!(many.getOccpupiedCount(), 2000);
+}
+
+#[test]
+fn to_bytes_shouldRoundTripThroughFromBytes() {
+    let mut field = DLAField::new_seeded("test".to_string(), 500, 60, 60, 11).unwrap();
+
+    for _ in 0..50 {
+        field.next_state();
+    }
+
+    let restored = DLAField::from_bytes(&field.to_bytes()).unwrap();
+    assert_eq!(restored, field);
+
+    // the rng comes along too, so both carry on the same way
+    let mut restored = restored;
+    field.next_state();
+    restored.next_state();
+    assert_eq!(restored, field);
+}
+
+#[test]
+fn from_bytes_shouldRejectTruncatedSnapshots() {
+    let field = DLAField::new_seeded("test".to_string(), 10, 20, 20, 1).unwrap();
+    let bytes = field.to_bytes();
+
+    assert!(DLAField::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(DLAField::from_bytes(b"nope").is_err());
+}
+
+#[test]
+fn to_json_shouldRoundTripThroughFromJson() {
+    let mut field = DLAField::new_seeded("test".to_string(), 300, 40, 40, 5).unwrap();
+
+    for _ in 0..30 {
+        field.next_state();
+    }
+
+    let json = field.to_json().unwrap();
+    assert_eq!(DLAField::from_json(&json).unwrap(), field);
+
+    // cells has to agree with the agents
+    let tampered = json.replacen("\"cells\":[\"", "\"cells\":[\"#", 1);
+    assert!(DLAField::from_json(&tampered).is_err());
+}
+
+#[test]
+fn from_agents_json_shouldAcceptEitherNamingStyle() {
+    let json = r#"[
+        { "x": 1, "y": 9, "state": "STUCK", "sticky_neighbor": null },
+        { "x": 2, "y": 9, "state": 1, "stickyNeighbor": { "x": 1, "y": 9 } },
+        { "x": 5, "y": 2 }
+    ]"#;
+
+    let field = DLAField::from_agents_json("test".to_string(), 10, 10, json).unwrap();
+    assert_eq!(field.getStuckCount(), 2);
+    assert_eq!(field.getOccpupiedCount(), 3);
+}
+
+#[test]
+fn from_agents_json_shouldReportThePathOfBadValues() {
+    let overlapping = r#"[{ "x": 1, "y": 1 }, { "x": 1, "y": 1 }]"#;
+    let out_of_bounds = r#"[{ "x": 1, "y": 10 }]"#;
+    let bad_neighbor = r#"[{ "x": 1, "y": 1, "state": "STUCK", "stickyNeighbor": { "x": "a", "y": 1 } }]"#;
+
+    let error = |json| DLAField::from_agents_json("test".to_string(), 10, 10, json).unwrap_err().to_string();
+
+    assert_eq!(error(overlapping), "agents[1]: (1, 1) is already taken by agents[0]");
+    assert_eq!(error(out_of_bounds), "agents[0]: (1, 10) is outside of the 10x10 field");
+    assert_eq!(error(bad_neighbor), "agents[0].sticky_neighbor.x: expected a non-negative integer, found \"a\"");
+}
+
+#[test]
+fn public_api_shouldReturnErrorsInsteadOfPanicking() {
+    let field = DLAField::new_seeded("test".to_string(), 10, 20, 20, 1).unwrap();
+
+    assert_eq!(
+        field.get_agent_at(10).unwrap_err(),
+        DlaError::AgentIndexOutOfRange { ndx: 10, num_agents: 10 }
+    );
+    assert_eq!(
+        DLAField::new_seeded("test".to_string(), 5, 2, 2, 1).unwrap_err(),
+        DlaError::TooManyAgents { num_agents: 5, capacity: 4 }
+    );
+
+    // fields that can't be allocated fail instead of aborting
+    assert!(DLAField::new("test".to_string(), 0, 1 << 20, 1 << 20).is_err());
+    assert!(DLAField::new_sparse("test".to_string(), 0, usize::MAX, 2).is_err());
+    assert!(DLAField::new_sparse("test".to_string(), 0, 1 << 30, 1 << 30).is_err());
+    assert!(DLAField::from_agents_json("test".to_string(), usize::MAX, usize::MAX, "[]").is_err());
+    assert!(DLAField::from_bitmap("test".to_string(), b"P1 100000 100000 0", &BitmapLegend::new()).is_err());
+
+    // and serializing hands back a Result rather than expecting it to work
+    assert!(DLAField::from_json(&field.to_json().unwrap()).is_ok());
+    let replay = Replay::record(&ReplayConfig::new("test".to_string(), 10, 20, 20, false, 1), 5, 5).unwrap();
+    assert!(Replay::from_json(&replay.to_json().unwrap()).is_ok());
+}
+
+#[test]
+fn validate_shouldHoldWhileTheFieldGrows() {
+    let mut dense = DLAField::new_seeded("test".to_string(), 1500, 80, 80, 5).unwrap();
+    let mut sparse = DLAField::new_sparse("test".to_string(), 300, 200, 200).unwrap();
+
+    for _ in 0..100 {
+        dense.next_state();
+        sparse.next_state();
+        assert_eq!(dense.validate(), Ok(()));
+        assert_eq!(sparse.validate(), Ok(()));
+    }
+}
+
+#[test]
+fn validate_shouldReportStickyNeighborsThatAreNotStuck() {
+    let json = r#"[
+        { "x": 1, "y": 9, "state": "STUCK", "sticky_neighbor": { "x": 2, "y": 9 } },
+        { "x": 2, "y": 9, "state": "FREE" }
+    ]"#;
+    let field = DLAField::from_agents_json("test".to_string(), 10, 10, json).unwrap();
+
+    match field.validate() {
+        Err(DlaError::Inconsistent(violations)) =>
+            assert_eq!(violations, vec!["agents[0]: sticky neighbor (2, 9) is not stuck".to_string()]),
+        other => panic!("expected an inconsistency, got {:?}", other)
+    }
+}
+
+#[test]
+fn next_state_shouldHandleWideAndTallFields() {
+    for &(width, height) in [(160, 40), (40, 160)].iter() {
+        let mut field = DLAField::new_seeded("test".to_string(), 800, width, height, 3).unwrap();
+        assert_eq!((field.get_width(), field.get_height()), (width, height));
+
+        let mut ticks = 0;
+        while field.next_state() && ticks < 5000 {
+            ticks += 1;
+        }
+
+        assert_eq!(field.validate(), Ok(()));
+        assert_eq!(field.getStuckCount(), 800);
+
+        for ndx in 0..field.get_num_agents() {
+            let agent = field.get_agent_at(ndx).unwrap();
+            assert!(agent.get_x() < width && agent.get_y() < height);
+
+            // everything grows up from the bottom row
+            if agent.get_sticky_neighbor().is_none() {
+                assert_eq!(agent.get_y(), height - 1);
+            }
+        }
+
+        assert_eq!(DLAField::from_bytes(&field.to_bytes()).unwrap(), field);
+        assert_eq!(DLAField::from_json(&field.to_json().unwrap()).unwrap(), field);
+    }
+}
+
+#[test]
+fn next_state_parallel_shouldMatchAcrossThreadCountsOnWideFields() {
+    let mut single = DLAField::new_seeded("test".to_string(), 600, 150, 30, 9).unwrap();
+    let mut many = DLAField::new_seeded("test".to_string(), 600, 150, 30, 9).unwrap();
+
+    for _ in 0..100 {
+        single.next_state_parallel(1);
+        many.next_state_parallel(6);
+    }
+
+    assert_eq!(single, many);
+    assert_eq!(many.validate(), Ok(()));
+}
+
+#[test]
+fn history_shouldRewindAndReplayRecordedTicks() {
+    let mut field = DLAField::new_seeded("test".to_string(), 400, 50, 50, 21).unwrap();
+    let mut history = History::new(&field, usize::MAX, 16);
+
+    let mut frames = vec![field.clone()];
+    for _ in 0..60 {
+        history.step(&mut field);
+        frames.push(field.clone());
+    }
+
+    for &tick in [0, 15, 16, 33, 60].iter() {
+        assert_eq!(history.field_at(tick).unwrap(), frames[tick]);
+    }
+
+    history.rewind(&mut field, 10).unwrap();
+    assert_eq!(field, frames[10]);
+
+    // played back from the deltas up to the newest tick, simulated after that
+    for frame in frames[11..].iter() {
+        history.step(&mut field);
+        assert_eq!(&field, frame);
+    }
+
+    let mut straight = frames[60].clone();
+    history.step(&mut field);
+    straight.next_state();
+    assert_eq!(field, straight);
+    assert_eq!(history.get_newest_tick(), 61);
+}
+
+#[test]
+fn history_shouldDropTheOldestTicksOverItsMemoryCap() {
+    let mut field = DLAField::new_seeded("test".to_string(), 400, 50, 50, 21).unwrap();
+    let mut history = History::new(&field, 40_000, 10);
+
+    for _ in 0..100 {
+        history.step(&mut field);
+    }
+
+    assert!(history.get_memory_used() <= 40_000);
+    assert!(history.get_oldest_tick() > 0);
+    assert_eq!(history.get_oldest_tick() % 10, 0);
+    assert_eq!(history.get_newest_tick(), 100);
+    assert_eq!(history.field_at(100).unwrap(), field);
+
+    match history.field_at(0) {
+        Err(DlaError::TickOutOfRange { tick: 0, newest: 100, .. }) => {},
+        other => panic!("expected the tick to be out of range, got {:?}", other)
+    }
+}
+
+#[test]
+fn replay_shouldRegenerateTheRecordedRun() {
+    let config = ReplayConfig::new("test".to_string(), 300, 60, 40, false, 17);
+    let replay = Replay::record(&config, 80, 20).unwrap();
+    let json = replay.to_json().unwrap();
+
+    let mut field = DLAField::new_seeded("test".to_string(), 300, 60, 40, 17).unwrap();
+    for _ in 0..80 {
+        field.next_state();
+    }
+
+    assert_eq!(Replay::from_json(&json).unwrap(), replay);
+    assert_eq!(verify_replay(&json).unwrap(), field);
+    // the run stops early once everything is stuck
+    assert!(replay.get_ticks() <= 80);
+    assert_eq!(replay.field_at(replay.get_ticks()).unwrap(), field);
+
+    let sparse = ReplayConfig::new("test".to_string(), 300, 60, 40, true, 17);
+    assert!(Replay::record(&sparse, 80, 20).unwrap().verify().is_ok());
+}
+
+#[test]
+fn verify_replay_shouldReportTheTickARunDivergesAt() {
+    let config = ReplayConfig::new("test".to_string(), 300, 60, 40, false, 17);
+    let json = Replay::record(&config, 80, 20).unwrap().to_json().unwrap();
+
+    let mut replay: serde_json::Value = serde_json::from_str(&json).unwrap();
+    replay["checkpoints"][1]["digest"] = "0000000000000000".into();
+
+    match verify_replay(&replay.to_string()) {
+        Err(DlaError::ReplayMismatch { tick: 40, expected, .. }) => assert_eq!(expected, "0000000000000000"),
+        other => panic!("expected a mismatch at tick 40, got {:?}", other)
+    }
+}
+
+#[test]
+fn autosave_shouldKeepTheNewestSnapshotsOnDisk() {
+    let dir = std::env::temp_dir().join(format!("dla-autosave-{}", std::process::id()));
+    let mut field = DLAField::new_seeded("test".to_string(), 300, 50, 50, 4).unwrap();
+    let mut autosave = Autosave::to_files(10, 0, &dir, "run", 3);
+
+    let mut at_tick_40 = None;
+    for tick in 1..=45 {
+        autosave.step(&mut field).unwrap();
+        if tick == 40 {
+            at_tick_40 = Some(field.clone());
+        }
+    }
+
+    let mut files: Vec<String> = std::fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(files, vec!["run-0000000020.dlaf", "run-0000000030.dlaf", "run-0000000040.dlaf"]);
+
+    let (tick, restored) = latest_autosave(&dir, "run").unwrap().unwrap();
+    assert_eq!(tick, 40);
+    assert_eq!(Some(restored), at_tick_40);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+struct CountingSink(std::rc::Rc<std::cell::RefCell<Vec<usize>>>);
+
+impl AutosaveSink for CountingSink {
+    fn save(&mut self, tick: usize, _snapshot: &[u8]) -> Result<(), DlaError> {
+        self.0.borrow_mut().push(tick);
+        Ok(())
+    }
+}
+
+#[test]
+fn autosave_shouldSaveAfterEnoughAgentsGetStuck() {
+    let ticks = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let mut field = DLAField::new_seeded("test".to_string(), 300, 50, 50, 4).unwrap();
+    let mut autosave = Autosave::new(0, 25, Box::new(CountingSink(ticks.clone())));
+
+    let mut stuck_at_save = vec![];
+    while autosave.step(&mut field).unwrap() {
+        if ticks.borrow().len() > stuck_at_save.len() {
+            stuck_at_save.push(field.getStuckCount());
+        }
+    }
+
+    assert!(!stuck_at_save.is_empty());
+    assert_eq!(autosave.get_saves(), ticks.borrow().len());
+
+    // every save comes after at least 25 more agents got stuck
+    let mut previous = 0;
+    for stuck in stuck_at_save {
+        assert!(stuck - previous >= 25);
+        previous = stuck;
+    }
+}
+
+#[test]
+fn autosave_shouldNotSaveOnTheFirstStepOfAResumedRun() {
+    let ticks = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let mut field = DLAField::new_seeded("test".to_string(), 300, 50, 50, 4).unwrap();
+    let mut tick = 0;
+    while field.getStuckCount() < 50 {
+        field.next_state();
+        tick += 1;
+    }
+
+    // picked back up from a snapshot of the run, which already has more than 25 agents stuck
+    let mut autosave = Autosave::new(0, 25, Box::new(CountingSink(ticks.clone())));
+    autosave.set_tick(tick, &field);
+    autosave.step(&mut field).unwrap();
+
+    assert_eq!(autosave.get_tick(), tick + 1);
+    assert_eq!(autosave.get_saves(), 0);
+    assert!(ticks.borrow().is_empty());
+}
+
+#[test]
+fn to_compressed_shouldRoundTripAndShrinkSaves() {
+    let mut field = DLAField::new_seeded("test".to_string(), 3000, 120, 100, 8).unwrap();
+    while field.next_state() {}
+
+    let compressed = field.to_compressed();
+    let restored = DLAField::from_compressed(&compressed).unwrap();
+
+    // agents come back in cell order, so compare them by position
+    let sorted_agents = |field: &DLAField| {
+        let mut agents: Vec<_> = (0..field.get_num_agents())
+            .map(|ndx| field.get_agent_at(ndx).unwrap())
+            .map(|agent| (agent.get_x(), agent.get_y(), agent.get_sticky_neighbor().map(|n| (n.x, n.y))))
+            .collect();
+        agents.sort();
+        agents
+    };
+
+    assert_eq!(restored.validate(), Ok(()));
+    assert_eq!(sorted_agents(&restored), sorted_agents(&field));
+    assert_eq!(restored.to_compressed(), compressed);
+    assert_eq!(DLAField::from_bytes(&compressed).unwrap(), restored);
+
+    assert!(compressed.len() * 10 < field.to_json().unwrap().len());
+    assert!(compressed.len() * 5 < field.to_bytes().len());
+}
+
+#[test]
+fn export_png_shouldRejectImagesTooLargeToBuild() {
+    let field = DLAField::new_seeded("test".to_string(), 10, 600, 400, 1).unwrap();
+    let mut options = PngOptions::new();
+
+    // each side is within what PNG allows, the pixels together are not
+    options.set_scale(1_000_000);
+    assert!(field.export_png(&options).is_err());
+    options.set_scale(50);
+    assert!(field.export_png(&options).is_err());
+
+    options.set_scale(4);
+    assert!(field.export_png(&options).is_ok());
+}
+
+#[test]
+fn export_png_shouldDrawEveryCellInThePaletteColors() {
+    let json = r#"[
+        { "x": 1, "y": 2, "state": "STUCK" },
+        { "x": 3, "y": 0, "state": "FREE" }
+    ]"#;
+    let field = DLAField::from_agents_json("test".to_string(), 5, 3, json).unwrap();
+
+    let mut options = PngOptions::new();
+    options.set_scale(2);
+    options.set_background(0, 0, 0, 255);
+    options.set_occupied_color(0, 255, 0, 255);
+    let png = field.export_png(&options).unwrap();
+
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(&png[16..24], &[0, 0, 0, 10, 0, 0, 0, 6]);
+
+    let idat_len = u32::from_be_bytes([png[33], png[34], png[35], png[36]]) as usize;
+    assert_eq!(&png[37..41], b"IDAT");
+    let raw = miniz_oxide::inflate::decompress_to_vec_zlib(&png[41..41 + idat_len]).unwrap();
+
+    let pixel = |x: usize, y: usize| {
+        let offset = y * (1 + 10 * 4) + 1 + x * 4;
+        [raw[offset], raw[offset + 1], raw[offset + 2], raw[offset + 3]]
+    };
+
+    assert_eq!(raw.len(), 6 * (1 + 10 * 4));
+    assert_eq!(pixel(0, 0), [0, 0, 0, 255]);
+    assert_eq!(pixel(2, 5), [255, 0, 0, 255]);
+    assert_eq!(pixel(3, 4), [255, 0, 0, 255]);
+    assert_eq!(pixel(7, 1), [0, 255, 0, 255]);
+    assert_eq!(pixel(9, 5), [0, 0, 0, 255]);
+    assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+}
+
+// a root at the bottom, a trunk of two and a fork at the top
+const SMALL_TREE: &str = r#"[
+    { "x": 1, "y": 4, "state": "STUCK" },
+    { "x": 1, "y": 3, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 4 } },
+    { "x": 1, "y": 2, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 3 } },
+    { "x": 2, "y": 1, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 2 } },
+    { "x": 0, "y": 1, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 2 } },
+    { "x": 4, "y": 0, "state": "FREE" }
+]"#;
+
+// a root at the bottom of a field wider than it is tall, with a branch running left
+const WIDE_TREE: &str = r#"[
+    { "x": 5, "y": 2, "state": "STUCK" },
+    { "x": 4, "y": 1, "state": "STUCK", "sticky_neighbor": { "x": 5, "y": 2 } },
+    { "x": 3, "y": 1, "state": "STUCK", "sticky_neighbor": { "x": 4, "y": 1 } },
+    { "x": 0, "y": 0, "state": "FREE" }
+]"#;
+
+fn small_tree() -> DLAField {
+    DLAField::from_agents_json("test".to_string(), 5, 5, SMALL_TREE).unwrap()
+}
+
+fn wide_tree() -> DLAField {
+    DLAField::from_agents_json("test".to_string(), 7, 3, WIDE_TREE).unwrap()
+}
+
+// no agents at all, and not square either
+fn empty_field() -> DLAField {
+    DLAField::from_agents_json("test".to_string(), 4, 3, "[]").unwrap()
+}
+
+// SMALL_TREE kept in tiles rather than one array
+fn sparse_small_tree() -> DLAField {
+    let mut json: serde_json::Value = serde_json::from_str(&small_tree().to_json().unwrap()).unwrap();
+    json["sparse"] = serde_json::json!(true);
+    json.as_object_mut().unwrap().remove("cells");
+
+    let field = DLAField::from_json(&json.to_string()).unwrap();
+    assert!(field.is_sparse());
+    field
+}
+
+// SMALL_TREE with walls filling its empty bottom right corner
+fn walled_small_tree() -> DLAField {
+    let mut field = small_tree();
+    field.add_walls(&[(3, 3), (4, 3), (3, 4), (4, 4)]).unwrap();
+    field
+}
+
+// every export drawn from the stuck agents and their links rather than the cells
+fn aggregate_exports(field: &DLAField) -> Vec<Vec<u8>> {
+    let mut outlines = DxfOptions::new();
+    outlines.set_outlines(true);
+
+    vec![
+        field.export_svg(&SvgOptions::new()).unwrap().into_bytes(),
+        field.export_graphml().unwrap().into_bytes(),
+        field.export_dot().unwrap().into_bytes(),
+        field.export_newick(false).unwrap().into_bytes(),
+        field.export_gcode(&GcodeOptions::new()).unwrap().into_bytes(),
+        field.export_dxf(&DxfOptions::new()).unwrap().into_bytes(),
+        field.export_dxf(&outlines).unwrap().into_bytes(),
+        field.export_stl(&StlOptions::new()).unwrap(),
+        field.export_csv().unwrap().into_bytes(),
+        field.export_ply(false).unwrap(),
+        field.export_npy_agents()
+    ]
+}
+
+#[test]
+fn exports_shouldNotDependOnHowTheFieldIsStored() {
+    let (dense, sparse) = (small_tree(), sparse_small_tree());
+
+    assert_eq!(aggregate_exports(&sparse), aggregate_exports(&dense));
+    assert_eq!(sparse.export_npy_cells().unwrap(), dense.export_npy_cells().unwrap());
+    assert_eq!(sparse.export_png(&PngOptions::new()).unwrap(), dense.export_png(&PngOptions::new()).unwrap());
+}
+
+#[test]
+fn aggregate_exports_shouldLeaveWallsOut() {
+    assert_eq!(aggregate_exports(&walled_small_tree()), aggregate_exports(&small_tree()));
+}
+
+#[test]
+fn export_svg_shouldLinkEveryStuckAgentToItsNeighbor() {
+    let mut options = SvgOptions::new();
+    options.set_scale(10.0);
+    let svg = small_tree().export_svg(&options).unwrap();
+
+    assert_eq!(svg.matches("<line ").count(), 4);
+    assert_eq!(svg.matches("<circle ").count(), 5);
+}
+
+#[test]
+fn export_svg_shouldStrokeBySubtreeAndColorByDepth() {
+    let mut options = SvgOptions::new();
+    options.set_scale(10.0);
+    options.set_stroke_by_subtree(true);
+    options.set_depth_colors(0, 0, 0, 0, 0, 255);
+    let svg = small_tree().export_svg(&options).unwrap();
+
+    // the trunk carries the three agents above it, the tips at depth 3 get the tip color
+    assert!(svg.contains(r#"<line x1="15" y1="45" x2="15" y2="35" stroke="rgb(0,0,85)" stroke-width="10"/>"#));
+    assert!(svg.contains(r#"<line x1="15" y1="25" x2="25" y2="15" stroke="rgb(0,0,255)" stroke-width="5"/>"#));
+}
+
+#[test]
+fn export_svg_shouldMergeChainsIntoPaths() {
+    let mut options = SvgOptions::new();
+    options.set_scale(10.0);
+    options.set_merge_paths(true);
+    let svg = small_tree().export_svg(&options).unwrap();
+
+    assert_eq!(svg.matches("<line ").count(), 0);
+    assert_eq!(svg.matches("<path ").count(), 3);
+    assert!(svg.contains(r#"<path d="M15 45 L15 35 L15 25""#));
+}
+
+#[test]
+fn export_svg_shouldSizeTheDrawingToTheField() {
+    let mut options = SvgOptions::new();
+    options.set_scale(10.0);
+    let svg = wide_tree().export_svg(&options).unwrap();
+
+    assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="70" height="30" viewBox="0 0 70 30">"#));
+    // the root sits in the bottom row, a branch cell in the middle one
+    assert!(svg.contains(r#"<line x1="55" y1="25" x2="45" y2="15""#));
+}
+
+#[test]
+fn export_svg_shouldDrawNothingForAnEmptyField() {
+    let svg = empty_field().export_svg(&SvgOptions::new()).unwrap();
+
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert!(!svg.contains("<line ") && !svg.contains("<circle ") && !svg.contains("<path "));
+}
+
+#[test]
+fn export_graphml_shouldWriteANodePerStuckAgentAndAnEdgePerLink() {
+    let graphml = small_tree().export_graphml().unwrap();
+
+    assert_eq!(graphml.matches("<node ").count(), 5);
+    assert_eq!(graphml.matches("<edge ").count(), 4);
+    assert!(graphml.contains(concat!(
+        r#"<node id="n3"><data key="x">2</data><data key="y">1</data><data key="depth">3</data>"#,
+        r#"<data key="subtree_size">1</data><data key="color">#ff0000</data><data key="alpha">100</data></node>"#)));
+    assert!(graphml.contains(r#"<edge source="n2" target="n4"/>"#));
+}
+
+#[test]
+fn export_graphml_shouldWriteAnEmptyGraphForAnEmptyField() {
+    let graphml = empty_field().export_graphml().unwrap();
+
+    assert!(graphml.contains("<graph "));
+    assert_eq!(graphml.matches("<node ").count(), 0);
+    assert_eq!(graphml.matches("<edge ").count(), 0);
+}
+
+#[test]
+fn export_dot_shouldWriteANodePerStuckAgentAndAnEdgePerLink() {
+    let dot = small_tree().export_dot().unwrap();
+
+    assert!(dot.starts_with("digraph dla {"));
+    assert!(dot.contains(r##"n0 [x=1, y=4, depth=0, subtree_size=5, color="#ff0000", pos="1,0!"];"##));
+    assert!(dot.contains("n0 -> n1;"));
+    assert_eq!(dot.matches(" -> ").count(), 4);
+}
+
+#[test]
+fn export_dot_shouldPlaceNodesFromTheBottomOfTheField() {
+    let dot = wide_tree().export_dot().unwrap();
+
+    // the field is 3 rows tall, so the root on row 2 is at the bottom and the branch one up
+    assert!(dot.contains(r#"x=5, y=2, depth=0"#) && dot.contains(r#"pos="5,0!""#));
+    assert!(dot.contains(r#"x=3, y=1, depth=2"#) && dot.contains(r#"pos="3,1!""#));
+}
+
+#[test]
+fn export_dot_shouldWriteAnEmptyGraphForAnEmptyField() {
+    let dot = empty_field().export_dot().unwrap();
+
+    assert!(dot.starts_with("digraph dla {"));
+    assert!(!dot.contains("n0"));
+}
+
+// the SMALL_TREE tree with a second root off to the side
+fn two_trees() -> DLAField {
+    let json = r#"[
+        { "x": 1, "y": 4, "state": "STUCK" },
+        { "x": 1, "y": 3, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 4 } },
+        { "x": 1, "y": 2, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 3 } },
+        { "x": 2, "y": 1, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 2 } },
+        { "x": 0, "y": 1, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 2 } },
+        { "x": 4, "y": 4, "state": "STUCK" }
+    ]"#;
+
+    DLAField::from_agents_json("test".to_string(), 5, 5, json).unwrap()
+}
+
+#[test]
+fn export_newick_shouldWriteOneTreePerRoot() {
+    assert_eq!(
+        two_trees().export_newick(false).unwrap(),
+        "(((x2y1:1.414214,x0y1:1.414214)x1y2:1)x1y3:1)x1y4;\nx4y4;\n"
+    );
+}
+
+#[test]
+fn export_newick_shouldCollapseChainsIntoLongerBranches() {
+    assert_eq!(
+        two_trees().export_newick(true).unwrap(),
+        "((x2y1:1.414214,x0y1:1.414214)x1y2:2)x1y4;\nx4y4;\n"
+    );
+}
+
+#[test]
+fn export_newick_shouldWriteNothingForAnEmptyField() {
+    assert_eq!(empty_field().export_newick(false).unwrap(), "");
+}
+
+// 70x50mm paper with a 5mm margin, a servo lifting the pen
+fn plotter_options() -> GcodeOptions {
+    let mut options = GcodeOptions::new();
+    options.set_paper_size(70.0, 50.0);
+    options.set_margin(5.0);
+    options.set_pen_commands("M3 S0".to_string(), "M3 S90".to_string());
+    options
+}
+
+#[test]
+fn export_gcode_shouldDrawOneContinuousStrokePerTip() {
+    let gcode = small_tree().export_gcode(&plotter_options()).unwrap();
+    let lines: Vec<&str> = gcode.lines().collect();
+
+    // the trunk and one tip in one go, the other tip from the fork
+    assert_eq!(lines.iter().filter(|line| **line == "M3 S90").count(), 2);
+    assert_eq!(lines.iter().filter(|line| line.starts_with("G1 ")).count(), 4);
+}
+
+#[test]
+fn export_gcode_shouldCenterTheCellsOnThePaper() {
+    let gcode = small_tree().export_gcode(&plotter_options()).unwrap();
+    let lines: Vec<&str> = gcode.lines().collect();
+
+    // 8mm cells centered on the paper, the trunk starts at the root on the bottom row
+    let first_down = lines.iter().position(|line| *line == "M3 S90").unwrap();
+    assert_eq!(lines[first_down - 1], "G0 X27 Y9 F3000");
+    assert_eq!(lines[first_down + 1], "G1 X27 Y17 F1500");
+}
+
+#[test]
+fn export_gcode_shouldFitTheLongerSideOfTheFieldToThePaper() {
+    let mut options = plotter_options();
+    options.set_paper_size(80.0, 40.0);
+    let gcode = wide_tree().export_gcode(&options).unwrap();
+    let lines: Vec<&str> = gcode.lines().collect();
+
+    // 10mm cells fill the 70x30mm inside the margin, the stroke starts at the end nearest the
+    // origin, the branch tip, and runs down to the root
+    let first_down = lines.iter().position(|line| *line == "M3 S90").unwrap();
+    assert_eq!(&lines[first_down - 1..first_down + 4], &[
+        "G0 X40 Y20 F3000", "M3 S90", "G1 X50 Y20 F1500", "G1 X60 Y10 F1500", "M3 S0"
+    ]);
+}
+
+#[test]
+fn export_gcode_shouldOnlyTravelForAnEmptyField() {
+    let gcode = empty_field().export_gcode(&plotter_options()).unwrap();
+
+    assert!(gcode.starts_with("; wasm-rust-dla, 0 strokes on 70x50mm\n"));
+    assert!(!gcode.contains("M3 S90") && !gcode.contains("G1 "));
+    assert!(gcode.ends_with("G0 X0 Y0 F3000\n"));
+}
+
+#[test]
+fn export_gcode_shouldRejectPaperTooSmallForTheMargins() {
+    let mut options = plotter_options();
+    options.set_paper_size(10.0, 10.0);
+
+    assert!(small_tree().export_gcode(&options).is_err());
+}
+
+#[test]
+fn export_dxf_shouldOnlyPutTheVersionInTheHeader() {
+    let dxf = small_tree().export_dxf(&DxfOptions::new()).unwrap();
+
+    // R12 headers only carry the version, units are millimeters by convention
+    assert!(dxf.starts_with("  0\nSECTION\n  2\nHEADER\n  9\n$ACADVER\n  1\nAC1009\n  0\nENDSEC\n"));
+    assert!(dxf.ends_with("  0\nENDSEC\n  0\nEOF\n"));
+}
+
+#[test]
+fn export_dxf_shouldDrawALinePerLink() {
+    let mut options = DxfOptions::new();
+    options.set_cell_size(2.0);
+    let dxf = small_tree().export_dxf(&options).unwrap();
+
+    assert_eq!(dxf.matches("\nLINE\n").count(), 4);
+    // from the root on the bottom row up the trunk, in millimeters with y pointing up
+    assert!(dxf.contains("LINE\n  8\nLINKS\n 10\n3\n 20\n1\n 30\n0\n 11\n3\n 21\n3\n 31\n0\n"));
+}
+
+#[test]
+fn export_dxf_shouldMeasureYFromTheBottomOfTheField() {
+    let dxf = wide_tree().export_dxf(&DxfOptions::new()).unwrap();
+
+    // the root is in the bottom one of 3 rows, the first branch cell up and to the left
+    assert!(dxf.contains("LINE\n  8\nLINKS\n 10\n5.5\n 20\n0.5\n 30\n0\n 11\n4.5\n 21\n1.5\n 31\n0\n"));
+}
+
+#[test]
+fn export_dxf_shouldOutlineCellsThatOnlyTouchAtACornerSeparately() {
+    let mut options = DxfOptions::new();
+    options.set_outlines(true);
+    let dxf = small_tree().export_dxf(&options).unwrap();
+
+    // the trunk and the two tips
+    assert_eq!(dxf.matches("\nPOLYLINE\n").count(), 3);
+    assert_eq!(dxf.matches("\nVERTEX\n").count(), 12);
+}
+
+#[test]
+fn export_dxf_shouldDrawNothingForAnEmptyField() {
+    let mut options = DxfOptions::new();
+    let links = empty_field().export_dxf(&options).unwrap();
+    options.set_outlines(true);
+    let outlines = empty_field().export_dxf(&options).unwrap();
+
+    for dxf in [links, outlines].iter() {
+        assert!(dxf.ends_with("  2\nENTITIES\n  0\nENDSEC\n  0\nEOF\n"));
+    }
+}
+
+#[test]
+fn export_dxf_shouldRejectCellsOfNoSize() {
+    let mut options = DxfOptions::new();
+    options.set_cell_size(0.0);
+
+    assert!(small_tree().export_dxf(&options).is_err());
+}
+
+#[test]
+fn stuck_outlines_shouldListHolesAsOutlinesOfTheirOwn() {
+    let ring = r#"[
+        { "x": 0, "y": 0, "state": "STUCK" }, { "x": 1, "y": 0, "state": "STUCK" }, { "x": 2, "y": 0, "state": "STUCK" },
+        { "x": 0, "y": 1, "state": "STUCK" }, { "x": 2, "y": 1, "state": "STUCK" },
+        { "x": 0, "y": 2, "state": "STUCK" }, { "x": 1, "y": 2, "state": "STUCK" }, { "x": 2, "y": 2, "state": "STUCK" }
+    ]"#;
+    let field = DLAField::from_agents_json("test".to_string(), 3, 3, ring).unwrap();
+
+    assert_eq!(field.stuck_outlines().unwrap(), vec![vec![(0, 0), (3, 0), (3, 3), (0, 3)], vec![(1, 1), (1, 2), (2, 2), (2, 1)]]);
+}
+
+fn stl_triangle_count(stl: &[u8]) -> u32 {
+    u32::from_le_bytes([stl[80], stl[81], stl[82], stl[83]])
+}
+
+// the x, y and z of every vertex
+fn stl_vertices(stl: &[u8]) -> Vec<[f32; 3]> {
+    stl[84..].chunks(50)
+        .flat_map(|triangle| (0..3).map(move |vertex| {
+            let value = |ndx: usize| {
+                let offset = 12 + vertex * 12 + ndx * 4;
+                f32::from_le_bytes([triangle[offset], triangle[offset + 1], triangle[offset + 2], triangle[offset + 3]])
+            };
+            [value(0), value(1), value(2)]
+        }))
+        .collect()
+}
+
+// checks that every edge of the mesh is walked exactly once in each direction, so it is closed and
+// manifold, and returns the volume it encloses
+fn closed_stl_volume(stl: &[u8]) -> f64 {
+    assert_eq!(stl.len(), 84 + stl_triangle_count(stl) as usize * 50);
+
+    let mut edges: HashMap<([u32; 3], [u32; 3]), (u32, u32)> = HashMap::new();
+    let mut volume = 0.0;
+
+    for vertices in stl_vertices(stl).chunks(3) {
+        for ndx in 0..3 {
+            let from = vertices[ndx].map(f32::to_bits);
+            let to = vertices[(ndx + 1) % 3].map(f32::to_bits);
+            let walks = edges.entry((from.min(to), from.max(to))).or_insert((0, 0));
+            if from < to {
+                walks.0 += 1;
+            } else {
+                walks.1 += 1;
+            }
+        }
+
+        let [a, b, c] = [vertices[0], vertices[1], vertices[2]].map(|vertex| vertex.map(f64::from));
+        volume += (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0]) + a[2] * (b[0] * c[1] - b[1] * c[0])) / 6.0;
+    }
+
+    assert!(edges.values().all(|&walks| walks == (1, 1)));
+
+    volume
+}
+
+#[test]
+fn export_stl_shouldBuildAClosedMeshOfColumns() {
+    let mut options = StlOptions::new();
+    options.set_cell_size(2.0);
+    let stl = small_tree().export_stl(&options).unwrap();
+
+    // the tips only touch the trunk at a corner, the strips along x = 1, x = 2 and y = 3 bridging
+    // them add 11 / 64 - 4 / 64² of a cell
+    let bridge = 1.0 / 64.0;
+    assert!((closed_stl_volume(&stl) - (5.0 + 11.0 * bridge - 4.0 * bridge * bridge) * 4.0).abs() < 1e-5);
+}
+
+#[test]
+fn export_stl_shouldRaiseColumnsByDepth() {
+    let mut options = StlOptions::new();
+    options.set_cell_size(2.0);
+    options.set_height_per_depth(1.0);
+    let stl = small_tree().export_stl(&options).unwrap();
+
+    // columns 1, 2, 3 and twice 4 tall, the strips as tall as the taller cell beside them
+    let bridge = 1.0 / 64.0;
+    assert!((closed_stl_volume(&stl) - (14.0 + 31.0 * bridge - 10.0 * bridge * bridge) * 4.0).abs() < 1e-5);
+}
+
+#[test]
+fn export_stl_with_shouldAskForTheHeightOfEveryStuckAgentOnce() {
+    let mut field = DLAField::new_seeded("test".to_string(), 800, 50, 50, 3).unwrap();
+    for _ in 0..300 {
+        field.next_state();
+    }
+
+    let asked = Cell::new(0);
+    let stl = field.export_stl_with(0.5, |agent, depth| {
+        asked.set(asked.get() + 1);
+        1.0 + (depth % 4) as f64 + (agent.get_x() % 3) as f64 / 2.0
+    }).unwrap();
+
+    assert_eq!(asked.get(), field.getStuckCount());
+    closed_stl_volume(&stl);
+}
+
+#[test]
+fn export_stl_shouldBridgeColumnsThatOnlyTouchDiagonally() {
+    let pair = r#"[
+        { "x": 0, "y": 1, "state": "STUCK" },
+        { "x": 1, "y": 0, "state": "STUCK", "sticky_neighbor": { "x": 0, "y": 1 } }
+    ]"#;
+    let field = DLAField::from_agents_json("test".to_string(), 2, 2, pair).unwrap();
+
+    // without the strips the edge at (1, 1) would have four walls, with them the two cells and a
+    // 1/32 cell wide cross between them make one solid
+    let bridge = 1.0 / 32.0;
+    let stl = field.export_stl(&StlOptions::new()).unwrap();
+    assert!((closed_stl_volume(&stl) - (2.0 + 2.0 * bridge - bridge * bridge / 2.0)).abs() < 1e-6);
+}
+
+#[test]
+fn export_stl_shouldMergeHeightsThatAreTheSameInSinglePrecision() {
+    let pair = r#"[
+        { "x": 0, "y": 0, "state": "STUCK" },
+        { "x": 1, "y": 0, "state": "STUCK", "sticky_neighbor": { "x": 0, "y": 0 } }
+    ]"#;
+    let field = DLAField::from_agents_json("test".to_string(), 2, 1, pair).unwrap();
+
+    // 1 + 1e-12 is 1 as a float, so the two columns are one box without a wall of no height
+    let stl = field.export_stl_with(1.0, |_, depth| 1.0 + depth as f64 * 1e-12).unwrap();
+    assert_eq!(stl_triangle_count(&stl), 12);
+    assert!((closed_stl_volume(&stl) - 2.0).abs() < 1e-6);
+}
+
+#[test]
+fn export_stl_shouldPutTheBottomRowOfTheFieldAtYZero() {
+    let stl = wide_tree().export_stl(&StlOptions::new()).unwrap();
+    let vertices = stl_vertices(&stl);
+
+    // the cells run from x 3 to 6 and fill the bottom two of the 3 rows, bridges stay inside that
+    let bounds = |axis: usize| vertices.iter()
+        .fold((f32::MAX, f32::MIN), |(low, high), vertex| (low.min(vertex[axis]), high.max(vertex[axis])));
+    assert_eq!(bounds(0), (3.0, 6.0));
+    assert_eq!(bounds(1), (0.0, 2.0));
+    assert_eq!(bounds(2), (0.0, 1.0));
+}
+
+#[test]
+fn export_stl_shouldWriteNoTrianglesForAnEmptyField() {
+    let stl = empty_field().export_stl(&StlOptions::new()).unwrap();
+
+    assert_eq!(stl.len(), 84);
+    assert_eq!(stl_triangle_count(&stl), 0);
+}
+
+// the agents of field on a sparse field of the given size, edit gets a go at the JSON first
+fn on_sparse_field(field: &DLAField, width: usize, height: usize, edit: impl FnOnce(&mut serde_json::Value)) -> DLAField {
+    let mut json: serde_json::Value = serde_json::from_str(&field.to_json().unwrap()).unwrap();
+    json["sparse"] = serde_json::json!(true);
+    json["width"] = serde_json::json!(width);
+    json["height"] = serde_json::json!(height);
+    json.as_object_mut().unwrap().remove("cells");
+    edit(&mut json);
+
+    DLAField::from_json(&json.to_string()).unwrap()
+}
+
+#[test]
+fn export_stl_shouldOnlyBuildTheMeshAroundTheStuckCells() {
+    let trunk = r#"[
+        { "x": 1, "y": 2, "state": "STUCK" },
+        { "x": 1, "y": 1, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 2 } },
+        { "x": 1, "y": 0, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 1 } }
+    ]"#;
+    let trunk = DLAField::from_agents_json("test".to_string(), 3, 3, trunk).unwrap();
+    let field = on_sparse_field(&trunk, 1 << 24, 1 << 24, |_| {});
+
+    // the trunk hangs from the top of a 2^24 cells tall field
+    let stl = field.export_stl(&StlOptions::new()).unwrap();
+    let top = (1u32 << 24) as f32;
+    assert!(stl_vertices(&stl).iter().all(|vertex| (1.0..=2.0).contains(&vertex[0]) && (top - 3.0..=top).contains(&vertex[1])));
+    assert!((closed_stl_volume(&stl) - 3.0).abs() < 1e-6);
+}
+
+#[test]
+fn export_stl_shouldRejectStuckCellsSpreadTooFarForAMesh() {
+    // a second root in the far corner of the sparse field
+    let field = on_sparse_field(&small_tree(), 1 << 24, 1 << 24, |json| {
+        json["agents"][5]["state"] = serde_json::json!("STUCK");
+        json["agents"][5]["x"] = serde_json::json!((1 << 24) - 1);
+        json["agents"][5]["y"] = serde_json::json!((1 << 24) - 1);
+    });
+
+    match field.export_stl(&StlOptions::new()) {
+        Err(DlaError::InvalidData(message)) => assert!(message.contains("span")),
+        other => panic!("expected InvalidData, got {:?}", other.map(|stl| stl.len()))
+    }
+}
+
+#[test]
+fn export_stl_shouldNotAllocateTheWholeOfASparseField() {
+    let field = DLAField::new_sparse("test".to_string(), 10, 1 << 24, 1 << 24).unwrap();
+
+    assert!(field.export_stl(&StlOptions::new()).is_ok());
+}
+
+#[test]
+fn export_stl_shouldRejectColumnsOfNoHeight() {
+    let mut options = StlOptions::new();
+    options.set_base_height(0.0);
+
+    assert!(small_tree().export_stl(&options).is_err());
+}
+
+const NPY_HEADER_LEN: usize = 128;
+
+#[test]
+fn export_npy_cells_shouldWriteTheGridRowByRow() {
+    let cells = small_tree().export_npy_cells().unwrap();
+
+    assert!(cells.starts_with(b"\x93NUMPY\x01\x00\x76\x00{'descr': '|u1', 'fortran_order': False, 'shape': (5, 5), }"));
+    assert_eq!(cells.len(), NPY_HEADER_LEN + 25);
+    // the top row holds the free agent, the tips are in the second one
+    assert_eq!(&cells[NPY_HEADER_LEN..NPY_HEADER_LEN + 10], &[0, 0, 0, 0, 1, 2, 0, 2, 0, 0]);
+}
+
+#[test]
+fn export_npy_cells_shouldShapeTheGridHeightByWidth() {
+    let cells = wide_tree().export_npy_cells().unwrap();
+
+    assert!(cells.starts_with(b"\x93NUMPY\x01\x00\x76\x00{'descr': '|u1', 'fortran_order': False, 'shape': (3, 7), }"));
+    assert_eq!(&cells[NPY_HEADER_LEN..], &[
+        1, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 2, 2, 0, 0,
+        0, 0, 0, 0, 0, 2, 0
+    ]);
+}
+
+#[test]
+fn export_npy_cells_shouldMarkWalls() {
+    let cells = walled_small_tree().export_npy_cells().unwrap();
+
+    // the bottom two rows end in two walls each
+    assert_eq!(&cells[NPY_HEADER_LEN + 15..], &[0, 2, 0, 3, 3, 0, 2, 0, 3, 3]);
+}
+
+#[test]
+fn export_npy_cells_shouldBeAllEmptyForAnEmptyField() {
+    let cells = empty_field().export_npy_cells().unwrap();
+
+    assert!(cells.starts_with(b"\x93NUMPY\x01\x00\x76\x00{'descr': '|u1', 'fortran_order': False, 'shape': (3, 4), }"));
+    assert_eq!(&cells[NPY_HEADER_LEN..], &[0; 12]);
+}
+
+#[test]
+fn export_npy_grids_shouldRejectFieldsTooLargeToSpellOut() {
+    let field = DLAField::new_sparse("test".to_string(), 10, 1 << 24, 1 << 24).unwrap();
+    let arrivals = ArrivalTimes::new(&field);
+
+    let too_large = |result: Result<Vec<u8>, DlaError>| matches!(result, Err(DlaError::InvalidData(message)) if message.contains("too large"));
+    assert!(too_large(field.export_npy_cells()));
+    assert!(too_large(field.export_npy_depth()));
+    assert!(too_large(arrivals.export_npy()));
+    assert!(too_large(field.export_npz(&arrivals)));
+    // the agent table is one row per agent however large the field
+    assert_eq!(field.export_npy_agents().len(), NPY_HEADER_LEN + 10 * 5 * 4);
+}
+
+#[test]
+fn export_npy_agents_shouldListAgentsWithTheirParents() {
+    let agents = small_tree().export_npy_agents();
+    let table: Vec<i32> = agents[NPY_HEADER_LEN..].chunks(4).map(|value| i32::from_le_bytes([value[0], value[1], value[2], value[3]])).collect();
+
+    // x, y, parent x, parent y, species for the root and the trunk agent above it
+    assert_eq!(table.len(), 6 * 5);
+    assert!(table.chunks(5).any(|row| row == [1, 4, -1, -1, 0]));
+    assert!(table.chunks(5).any(|row| row == [1, 3, 1, 4, 0]));
+}
+
+#[test]
+fn export_npy_agents_shouldWriteAnEmptyTableForAnEmptyField() {
+    let agents = empty_field().export_npy_agents();
+
+    assert!(agents.starts_with(b"\x93NUMPY\x01\x00\x76\x00{'descr': '<i4', 'fortran_order': False, 'shape': (0, 5), }"));
+    assert_eq!(agents.len(), NPY_HEADER_LEN);
+}
+
+#[test]
+fn arrival_times_shouldCountTicksAndRejectCellsOffTheField() {
+    let mut field = DLAField::new_seeded("test".to_string(), 300, 30, 20, 3).unwrap();
+    let mut arrivals = ArrivalTimes::new(&field);
+    for _ in 0..50 {
+        arrivals.step(&mut field).unwrap();
+    }
+
+    assert_eq!(arrivals.get_tick(), 50);
+    assert!(arrivals.get_arrival(0, 20).is_err());
+}
+
+#[test]
+fn export_npz_shouldHoldEveryGrid() {
+    let mut field = DLAField::new_seeded("test".to_string(), 300, 30, 20, 3).unwrap();
+    let mut arrivals = ArrivalTimes::new(&field);
+    for _ in 0..50 {
+        arrivals.step(&mut field).unwrap();
+    }
+
+    let npz = field.export_npz(&arrivals).unwrap();
+    assert!(npz.starts_with(b"PK\x03\x04"));
+    for name in ["cells.npy", "depth.npy", "arrival.npy", "agents.npy"].iter() {
+        assert_eq!(npz.windows(name.len()).filter(|window| window == &name.as_bytes()).count(), 2);
+    }
+}
+
+#[test]
+fn export_npz_shouldRejectArrivalsOfAnotherField() {
+    let field = DLAField::new_seeded("test".to_string(), 300, 30, 20, 3).unwrap();
+    let arrivals = ArrivalTimes::new(&field);
+    let other = DLAField::new_seeded("test".to_string(), 10, 20, 20, 3).unwrap();
+
+    assert!(other.export_npz(&arrivals).is_err());
+}
+
+#[test]
+fn export_csv_shouldHaveARowPerAgent() {
+    let csv = small_tree().export_csv().unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[0], "x,y,state,r,g,b,a,parent_x,parent_y,depth");
+    assert!(lines.contains(&"1,4,STUCK,255,0,0,100,,,0"));
+    assert!(lines.contains(&"2,1,STUCK,255,0,0,100,1,2,3"));
+    assert!(lines.contains(&"4,0,FREE,255,0,0,100,,,"));
+}
+
+#[test]
+fn export_csv_shouldOnlyHaveTheHeaderForAnEmptyField() {
+    assert_eq!(empty_field().export_csv().unwrap(), "x,y,state,r,g,b,a,parent_x,parent_y,depth\n");
+}
+
+#[test]
+fn export_ply_shouldWriteAVertexPerAgentInAscii() {
+    let ascii = String::from_utf8(small_tree().export_ply(false).unwrap()).unwrap();
+
+    assert!(ascii.starts_with("ply\nformat ascii 1.0\n"));
+    assert!(ascii.contains("element vertex 6\n"));
+    assert!(ascii.contains("property uchar red\n"));
+    assert!(ascii.contains("\n2 1 0 1 255 0 0 100 1 2 3\n"));
+    assert!(ascii.contains("\n4 0 0 0 255 0 0 100 -1 -1 -1\n"));
+}
+
+#[test]
+fn export_ply_shouldWriteAVertexPerAgentInBinary() {
+    let binary = small_tree().export_ply(true).unwrap();
+    let header_end = binary.windows(11).position(|window| window == b"end_header\n").unwrap() + 11;
+
+    // 12 bytes of coordinates, 5 of state and color and 12 of integers per agent
+    assert_eq!(binary.len() - header_end, 6 * 29);
+}
+
+#[test]
+fn export_ply_shouldWriteNoVerticesForAnEmptyField() {
+    let ascii = String::from_utf8(empty_field().export_ply(false).unwrap()).unwrap();
+
+    assert!(ascii.contains("element vertex 0\n"));
+    assert!(ascii.ends_with("end_header\n"));
+}
+
+#[test]
+fn export_png_shouldReadBackAsAnEmptyFieldForAnEmptyField() {
+    let png = empty_field().export_png(&PngOptions::new()).unwrap();
+    let field = DLAField::from_bitmap("test".to_string(), &png, &BitmapLegend::new()).unwrap();
+
+    assert_eq!((field.get_width(), field.get_height()), (4, 3));
+    assert_eq!(field.get_num_agents(), 0);
+    assert_eq!(field.get_wall_count(), 0);
+}
+
+#[test]
+fn from_bitmap_shouldReadSeedsWallsAndWalkers() {
+    // a wall across the middle with a gap, a row of walkers above it
+    let pbm = b"P1\n# walls\n6 4\n0 0 0 0 0 0\n000000\n111001\n0 0 0 0 0 0\n";
+    let mut legend = BitmapLegend::new();
+    let field = DLAField::from_bitmap_seeded("test".to_string(), pbm, &legend, 1).unwrap();
+    assert_eq!((field.get_width(), field.get_height()), (6, 4));
+    assert_eq!(field.get_wall_count(), 4);
+    assert!(field.is_wall(0, 2) && !field.is_wall(3, 2));
+    assert_eq!(field.get_num_agents(), 0);
+
+    // P4 packs the same rows into a byte each, black as stuck seeds this time
+    legend.set_color(0, 0, 0, BitmapCell::STUCK);
+    let packed = [&b"P4 6 4\n"[..], &[0b0000_0000, 0b0000_0000, 0b1110_0100, 0b0000_0000]].concat();
+    let seeded = DLAField::from_bitmap("test".to_string(), &packed, &legend).unwrap();
+    assert_eq!(seeded.getStuckCount(), 4);
+    assert_eq!(seeded.get_wall_count(), 0);
+
+    assert!(DLAField::from_bitmap("test".to_string(), b"P1 6 4 0 1", &legend).is_err());
+    assert!(DLAField::from_bitmap("test".to_string(), b"GIF89a", &legend).is_err());
+
+    // walkers never end up inside a wall and walls come back from every save format
+    let json = r#"[{ "x": 3, "y": 0 }, { "x": 4, "y": 0 }, { "x": 0, "y": 3, "state": "STUCK" }]"#;
+    let mut saved: serde_json::Value = serde_json::from_str(&DLAField::from_agents_json("test".to_string(), 6, 4, json).unwrap().to_json().unwrap()).unwrap();
+    saved["walls"] = serde_json::json!([[0, 2], [1, 2], [2, 2], [5, 2]]);
+    saved.as_object_mut().unwrap().remove("cells");
+    let mut field = DLAField::from_json(&saved.to_string()).unwrap();
+
+    // and the same field drawn as a PNG in the legend's colors reads back cell for cell
+    let mut options = PngOptions::new();
+    options.set_background(255, 255, 255, 255);
+    options.set_occupied_color(0, 255, 0, 255);
+    options.set_wall_color(0, 0, 0, 255);
+    let from_png = DLAField::from_bitmap("test".to_string(), &field.export_png(&options).unwrap(), &BitmapLegend::new()).unwrap();
+    assert_eq!(from_png.export_npy_cells().unwrap(), field.export_npy_cells().unwrap());
+
+    for _ in 0..50 {
+        field.next_state();
+        field.validate().unwrap();
+    }
+    assert_eq!(field.get_wall_count(), 4);
+    assert_eq!(DLAField::from_bytes(&field.to_bytes()).unwrap(), field);
+    assert_eq!(DLAField::from_json(&field.to_json().unwrap()).unwrap(), field);
+    assert_eq!(DLAField::from_compressed(&field.to_compressed()).unwrap().export_npy_cells().unwrap(), field.export_npy_cells().unwrap());
+}
+
+// the CRC-32 PNG chunks end with
+fn png_crc(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// length, type, data and CRC of one PNG chunk
+fn png_chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(chunk_type);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&png_crc(&chunk[4..]).to_be_bytes());
+    chunk
+}
+
+// a PNG with an extra chunk right after IHDR, which is 8 + 25 bytes in
+fn png_with_chunk(png: &[u8], chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    [&png[..33], &png_chunk(chunk_type, data)[..], &png[33..]].concat()
+}
+
+#[test]
+fn from_bitmap_shouldRejectImagesTooLargeForAFieldBeforeInflatingThem() {
+    // a 1 bit gray 32768x32768 image is a gigapixel, its zeros squeeze into a few hundred bytes
+    let mut header = vec![];
+    header.extend_from_slice(&32768u32.to_be_bytes());
+    header.extend_from_slice(&32768u32.to_be_bytes());
+    header.extend_from_slice(&[1, 0, 0, 0, 0]);
+    let zeros = miniz_oxide::deflate::compress_to_vec_zlib(&[0; 1 << 20], 10);
+    let png = [
+        &b"\x89PNG\r\n\x1a\n"[..],
+        &png_chunk(b"IHDR", &header),
+        &png_chunk(b"IDAT", &zeros),
+        &png_chunk(b"IEND", &[])
+    ].concat();
+    assert!(png.len() < 4096);
+
+    match DLAField::from_bitmap("test".to_string(), &png, &BitmapLegend::new()) {
+        Err(DlaError::InvalidData(message)) => assert!(message.contains("too large")),
+        other => panic!("expected InvalidData, got {:?}", other.map(|field| field.get_width()))
+    }
+}
+
+#[test]
+fn from_bitmap_shouldRejectMalformedPalettes() {
+    let field = DLAField::from_agents_json("test".to_string(), 5, 5, SMALL_TREE).unwrap();
+    let png = field.export_png(&PngOptions::new()).unwrap();
+    assert!(DLAField::from_bitmap("test".to_string(), &png_with_chunk(&png, b"PLTE", &[0; 6]), &BitmapLegend::new()).is_ok());
+
+    // not whole rgb entries, and more than 256 of them
+    for palette in [vec![0; 4], vec![0; 257 * 3]].iter() {
+        let result = DLAField::from_bitmap("test".to_string(), &png_with_chunk(&png, b"PLTE", palette), &BitmapLegend::new());
+        assert!(result.unwrap_err().to_string().contains("PLTE length"));
+    }
+}
+
+#[test]
+fn add_walls_shouldLeaveTheFieldAsItWasWhenAWallFails() {
+    let mut field = DLAField::from_agents_json("test".to_string(), 5, 5, SMALL_TREE).unwrap();
+
+    // the first wall is fine, the second sits on the root
+    assert!(field.add_walls(&[(0, 0), (1, 4)]).is_err());
+    assert_eq!(field.get_wall_count(), 0);
+    assert!(!field.is_wall(0, 0));
+    field.validate().unwrap();
+}
+
+#[test]
+fn from_bytes_shouldStillReadVersion1Snapshots() {
+    let mut field = DLAField::from_agents_json("test".to_string(), 5, 5, SMALL_TREE).unwrap();
+
+    // the version follows the 4 byte magic
+    let mut snapshot = field.to_bytes();
+    assert_eq!(&snapshot[4..6], &[2, 0]);
+    snapshot[4] = 1;
+    assert_eq!(DLAField::from_bytes(&snapshot).unwrap(), field);
+
+    let mut compressed = field.to_compressed();
+    assert_eq!(&compressed[4..6], &[2, 0]);
+    compressed[4] = 1;
+    assert_eq!(DLAField::from_compressed(&compressed).unwrap().to_compressed(), field.to_compressed());
+
+    // walls came with version 2
+    field.add_walls(&[(4, 4)]).unwrap();
+    let mut snapshot = field.to_bytes();
+    snapshot[4] = 1;
+    assert!(DLAField::from_bytes(&snapshot).is_err());
+    let mut compressed = field.to_compressed();
+    compressed[4] = 1;
+    assert!(DLAField::from_compressed(&compressed).is_err());
+
+    snapshot[4] = 3;
+    assert!(DLAField::from_bytes(&snapshot).is_err());
+}
+
+// position_hash as one byte per cell, row by row, read back out of the cells array of the NumPy
+// export: 0 empty, 1 free, 2 stuck, 3 wall
+fn cell_states(field: &DLAField) -> Vec<u8> {
+    let npy = field.export_npy_cells().unwrap();
+    let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+
+    npy[10 + header_len..].to_vec()
+}
+
+fn cell_pairs(flat: Vec<u32>) -> Vec<(usize, usize)> {
+    flat.chunks(2).map(|pair| (pair[0] as usize, pair[1] as usize)).collect()
+}
+
+// the cell states a change set lists, keyed by cell, with every cell listed at most once
+fn listed_states(mut changes: ChangeSet) -> HashMap<(usize, usize), u8> {
+    let mut listed = HashMap::new();
+    let lists = [(changes.take_vacated(), 0), (changes.take_occupied(), 1), (changes.take_stuck(), 2), (changes.take_walls(), 3)];
+
+    for (cells, state) in lists.iter() {
+        for cell in cell_pairs(cells.clone()) {
+            assert_eq!(listed.insert(cell, *state), None, "{:?} is listed twice", cell);
+        }
+    }
+
+    listed
+}
+
+// a field built from agents with its rng set to seed, so the walks that follow are fixed
+fn seeded_from_agents(width: usize, height: usize, agents: &str, seed: u64) -> DLAField {
+    let mut json: serde_json::Value = serde_json::from_str(
+        &DLAField::from_agents_json("test".to_string(), width, height, agents).unwrap().to_json().unwrap()).unwrap();
+    json["rng_state"] = serde_json::json!(seed.to_string());
+
+    DLAField::from_json(&json.to_string()).unwrap()
+}
+
+#[test]
+fn set_track_changes_shouldSeedTheFirstChangeSetWithEveryAgent() {
+    let json = r#"[
+        { "x": 1, "y": 9, "state": "STUCK" },
+        { "x": 2, "y": 8, "state": "STUCK", "sticky_neighbor": { "x": 1, "y": 9 } },
+        { "x": 5, "y": 2 }
+    ]"#;
+    let mut field = DLAField::from_agents_json("test".to_string(), 10, 10, json).unwrap();
+    assert!(field.take_changes().is_empty());
+
+    field.set_track_changes(true);
+    let mut changes = field.take_changes();
+    assert_eq!(cell_pairs(changes.take_occupied()), vec![(5, 2)]);
+    assert_eq!(cell_pairs(changes.take_stuck()), vec![(1, 9), (2, 8)]);
+    assert!(changes.take_vacated().is_empty());
+
+    // the arrays move out of the change set, asking again comes back empty
+    assert!(changes.take_occupied().is_empty());
+    assert!(field.take_changes().is_empty());
+}
+
+#[test]
+fn take_changes_shouldListEveryCellNextStateChanged() {
+    let mut field = DLAField::new_seeded("test".to_string(), 400, 30, 30, 3).unwrap();
+    field.set_track_changes(true);
+    field.take_changes();
+
+    for _ in 0..20 {
+        let before = cell_states(&field);
+        field.next_state();
+        let after = cell_states(&field);
+        let listed = listed_states(field.take_changes());
+
+        for (ndx, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+            let cell = (ndx % 30, ndx / 30);
+
+            if old != new {
+                assert_eq!(listed.get(&cell), Some(new), "{:?} changed but isn't listed as it is now", cell);
+            }
+        }
+        for (&(x, y), state) in listed.iter() {
+            assert_eq!(after[y * 30 + x], *state, "({}, {}) is listed in a state it isn't in", x, y);
+        }
+    }
+}
+
+#[test]
+fn take_changes_shouldListACellVacatedAndEnteredInOneTickAsOccupied() {
+    // the walker at (0, 1) goes first and steps down and right to (1, 2), the one at (1, 0) can
+    // only step down and left, into the cell just given up
+    let json = r#"[{ "x": 0, "y": 1 }, { "x": 1, "y": 0 }]"#;
+    let mut field = seeded_from_agents(2, 4, json, 2);
+    field.set_track_changes(true);
+    field.take_changes();
+
+    field.next_state();
+    assert_eq!(cell_states(&field), vec![0, 0, 1, 0, 0, 1, 0, 0]);
+
+    let mut changes = field.take_changes();
+    assert_eq!(cell_pairs(changes.take_occupied()), vec![(0, 1), (1, 2)]);
+    assert_eq!(cell_pairs(changes.take_vacated()), vec![(1, 0)]);
+}
+
+#[test]
+fn take_changes_shouldKeepTheLastStateOfCellsChangedOverSeveralTicks() {
+    let mut field = DLAField::new_seeded("test".to_string(), 400, 30, 30, 9).unwrap();
+    field.set_track_changes(true);
+    field.take_changes();
+
+    let before = cell_states(&field);
+    let mut left_and_refilled = 0;
+    let mut during = vec![];
+
+    for _ in 0..5 {
+        field.next_state();
+        during.push(cell_states(&field));
+    }
+
+    let after = cell_states(&field);
+    let listed = listed_states(field.take_changes());
+
+    for ndx in 0..before.len() {
+        let emptied_on_the_way = during.iter().any(|states| states[ndx] == 0);
+
+        if before[ndx] == 1 && after[ndx] == 1 && emptied_on_the_way {
+            left_and_refilled += 1;
+            assert_eq!(listed.get(&(ndx % 30, ndx / 30)), Some(&1));
+        }
+    }
+    assert!(left_and_refilled > 0);
+}
+
+#[test]
+fn take_changes_shouldLineStuckParentsUpWithStuckCells() {
+    let mut field = DLAField::new_seeded("test".to_string(), 300, 20, 20, 4).unwrap();
+    field.set_track_changes(true);
+    field.take_changes();
+
+    for _ in 0..60 {
+        field.next_state();
+    }
+
+    let mut changes = field.take_changes();
+    let stuck = cell_pairs(changes.take_stuck());
+    let parents = changes.take_stuck_parents();
+    assert_eq!(parents.len(), stuck.len() * 2);
+    assert!(!stuck.is_empty());
+
+    let agents: HashMap<(usize, usize), _> = (0..field.get_num_agents())
+        .map(|ndx| field.get_agent_at(ndx).unwrap())
+        .map(|agent| ((agent.get_x(), agent.get_y()), agent))
+        .collect();
+
+    for (&(x, y), parent) in stuck.iter().zip(parents.chunks(2)) {
+        let expected = match agents[&(x, y)].get_sticky_neighbor() {
+            None => [ChangeSet::get_no_parent(); 2],
+            Some(neighbor) => [neighbor.x as u32, neighbor.y as u32]
+        };
+        assert_eq!(parent, expected);
+    }
+}
+
+#[test]
+fn new_sparse_shouldOnlyAllocateTilesWithAgents() {
+    let mut field = DLAField::new_sparse("test".to_string(), 10, 100_000, 100_000).unwrap();
+    assert!(field.is_sparse());
+    // ten agents touch at most ten 64x64 tiles
+    assert!(field.get_allocated_cells() <= 10 * 64 * 64);
+
+    for _ in 0..20 {
+        field.next_state();
+    }
+    assert_eq!(field.getOccpupiedCount(), 10);
+    assert!(field.get_allocated_cells() <= 10 * 64 * 64);
+}
+
+#[test]
+fn new_sparse_shouldStepTheSameAsADenseField() {
+    let dense = DLAField::new_seeded("test".to_string(), 600, 150, 90, 12).unwrap();
+    let mut json: serde_json::Value = serde_json::from_str(&dense.to_json().unwrap()).unwrap();
+    json["sparse"] = serde_json::json!(true);
+    json.as_object_mut().unwrap().remove("cells");
+
+    let mut dense = dense;
+    let mut sparse = DLAField::from_json(&json.to_string()).unwrap();
+    assert!(sparse.is_sparse());
+
+    for _ in 0..150 {
+        dense.next_state();
+        sparse.next_state();
+    }
+    assert_eq!(sparse.export_npy_cells().unwrap(), dense.export_npy_cells().unwrap());
+    assert_eq!(sparse.export_npy_agents(), dense.export_npy_agents());
+    assert_eq!(sparse.getStuckCount(), dense.getStuckCount());
+}
+
+#[test]
+fn get_position_hash_shouldFailOnSparseFields() {
+    let dense = DLAField::new_seeded("test".to_string(), 10, 20, 20, 1).unwrap();
+    let sparse = DLAField::new_sparse("test".to_string(), 10, 20, 20).unwrap();
+
+    assert!(dense.get_position_hash().is_ok());
+    assert_eq!(sparse.get_position_hash().unwrap_err(), DlaError::SparseField);
+}
+
+#[test]
+fn next_state_parallel_shouldMatchWhenTheAgentsDoNotSplitEvenly() {
+    // 10 columns over 3 threads, and a thread count higher than the number of agents
+    let mut three = DLAField::new_seeded("test".to_string(), 61, 10, 40, 2).unwrap();
+    let mut many = DLAField::new_seeded("test".to_string(), 61, 10, 40, 2).unwrap();
+
+    for _ in 0..100 {
+        three.next_state_parallel(3);
+        many.next_state_parallel(64);
+    }
+
+    assert_eq!(three, many);
+    three.validate().unwrap();
+}
+
+// every agent on the field by cell
+fn agents_by_cell(field: &DLAField) -> HashMap<(usize, usize), ColorizedPoint> {
+    (0..field.get_num_agents())
+        .map(|ndx| field.get_agent_at(ndx).unwrap())
+        .map(|agent| ((agent.get_x(), agent.get_y()), agent))
+        .collect()
+}
+
+// Runs one tick and checks every free agent stuck, or didn't, the way the cell by cell neighbor
+// scan did before the bitsets: on the bottom row it becomes a root, otherwise it sticks to the
+// first neighbor, column by column and top down, that is stuck or is a walker on the bottom row
+fn step_and_check_sticking(field: &mut DLAField) {
+    let (width, height) = (field.get_width(), field.get_height());
+    let states = cell_states(field);
+    let before = agents_by_cell(field);
+    field.next_state();
+    let after = agents_by_cell(field);
+
+    for (&(x, y), agent) in before.iter() {
+        if agent.get_agent_state() == AgentState::STUCK {
+            continue;
+        }
+
+        let mut expected = if y == height - 1 { Some(None) } else { None };
+        for neighbor_x in x.saturating_sub(1)..(x + 2).min(width) {
+            for neighbor_y in y.saturating_sub(1)..(y + 2).min(height) {
+                let state = states[neighbor_y * width + neighbor_x];
+
+                if expected.is_none() && (state == 2 || (state == 1 && neighbor_y == height - 1 && (neighbor_x, neighbor_y) != (x, y))) {
+                    expected = Some(Some((neighbor_x, neighbor_y)));
+                }
+            }
+        }
+
+        match expected {
+            Some(parent) => {
+                let stuck = after[&(x, y)];
+                assert_eq!(stuck.get_agent_state(), AgentState::STUCK, "({}, {}) should have stuck", x, y);
+                assert_eq!(stuck.get_sticky_neighbor().map(|neighbor| (neighbor.x, neighbor.y)), parent, "parent of ({}, {})", x, y);
+            },
+            None => if let Some(agent) = after.get(&(x, y)) {
+                assert_eq!(agent.get_agent_state(), AgentState::FREE, "({}, {}) shouldn't have stuck", x, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn next_state_shouldStickAtTheEdgesOfTheFieldAndOfTheBitsetWords() {
+    let json = r#"[
+        { "x": 0, "y": 63 }, { "x": 1, "y": 64, "state": "STUCK" },
+        { "x": 4, "y": 64 }, { "x": 3, "y": 63, "state": "STUCK" },
+        { "x": 2, "y": 0 }, { "x": 1, "y": 1, "state": "STUCK" },
+        { "x": 2, "y": 128 }, { "x": 3, "y": 129 }
+    ]"#;
+    let mut field = DLAField::from_agents_json("test".to_string(), 5, 130, json).unwrap();
+
+    step_and_check_sticking(&mut field);
+
+    let parents: HashMap<(usize, usize), Option<(usize, usize)>> = agents_by_cell(&field).into_iter()
+        .filter(|(_, agent)| agent.get_agent_state() == AgentState::STUCK)
+        .map(|(cell, agent)| (cell, agent.get_sticky_neighbor().map(|neighbor| (neighbor.x, neighbor.y))))
+        .collect();
+    assert_eq!(parents[&(0, 63)], Some((1, 64)));
+    assert_eq!(parents[&(4, 64)], Some((3, 63)));
+    assert_eq!(parents[&(2, 0)], Some((1, 1)));
+    // the walker above a walker on the bottom row sticks to it as it becomes a root
+    assert_eq!(parents[&(2, 128)], Some((3, 129)));
+    assert_eq!(parents[&(3, 129)], None);
+    assert_eq!(field.getStuckCount(), 8);
+}
+
+#[test]
+fn next_state_shouldStickTheSameAsACellByCellScan() {
+    for &(width, height, seed) in [(7, 130, 1), (64, 65, 2), (3, 200, 3)].iter() {
+        let mut field = DLAField::new_seeded("test".to_string(), width * height / 4, width, height, seed).unwrap();
+
+        for _ in 0..60 {
+            step_and_check_sticking(&mut field);
+
+            // the counts come from the bitsets and have to follow the agents
+            let agents = agents_by_cell(&field);
+            let stuck = agents.values().filter(|agent| agent.get_agent_state() == AgentState::STUCK).count();
+            assert_eq!(field.getOccpupiedCount() as usize, agents.len());
+            assert_eq!(field.getStuckCount() as usize, stuck);
+        }
+    }
+}
+
+#[test]
+fn loaders_shouldRejectFieldsTooLargeToAllocate() {
+    let header = |magic: &[u8], flags: u8, side: u32| {
+        let mut bytes = magic.to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.push(flags);
+        bytes.extend_from_slice(&side.to_le_bytes());
+        bytes.extend_from_slice(&side.to_le_bytes());
+        // no canvas id, an rng state and no agents
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes
+    };
+    let too_large = |result: Result<DLAField, DlaError>| match result {
+        Err(DlaError::InvalidData(message)) => assert!(message.contains("too large"), "{}", message),
+        other => panic!("expected the field to be too large, got {:?}", other.map(|_| ()))
+    };
+
+    for &flags in [0, 1].iter() {
+        too_large(DLAField::from_bytes(&header(b"DLAF", flags, u32::MAX)));
+
+        // no runs, parents, far parents or palette, then a run of walls over every cell
+        let compressed = header(b"DLAC", flags, u32::MAX);
+        too_large(DLAField::from_compressed(&[&compressed[..], &[0, 0, 0]].concat()));
+        too_large(DLAField::from_compressed(&[&compressed[..], &[1, 0xff, 0xff, 0xff, 0xff, 0x0f]].concat()));
+    }
+
+    // a sparse field can be that large, but not filled with 2^25 walls
+    let walls = [&header(b"DLAC", 1, 1 << 20)[..], &[1, 0x83, 0x80, 0x80, 0x40]].concat();
+    match DLAField::from_compressed(&walls) {
+        Err(DlaError::InvalidData(message)) => assert!(message.ends_with("walls"), "{}", message),
+        other => panic!("expected too many walls, got {:?}", other.map(|_| ()))
+    }
+
+    too_large(DLAField::from_json(r#"{ "version": 1, "width": 4294967295, "height": 4294967295,
+        "canvas_id": "test", "rng_state": "1", "agents": [] }"#));
+    too_large(DLAField::from_json(r#"{ "version": 1, "width": 100000, "height": 100000, "sparse": false,
+        "canvas_id": "test", "rng_state": "1", "agents": [] }"#));
+}
+
+#[test]
+fn to_json_shouldOnlySpellOutCellsForDenseFields() {
+    let mut sparse = DLAField::new_sparse("test".to_string(), 50, 300, 200).unwrap();
+    sparse.next_state();
+
+    let json: serde_json::Value = serde_json::from_str(&sparse.to_json().unwrap()).unwrap();
+    assert_eq!(json["sparse"], serde_json::json!(true));
+    assert!(json.get("cells").is_none());
+    assert_eq!(DLAField::from_json(&sparse.to_json().unwrap()).unwrap(), sparse);
+
+    let dense = DLAField::new_seeded("test".to_string(), 50, 30, 20, 1).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&dense.to_json().unwrap()).unwrap();
+    assert_eq!(json["cells"].as_array().unwrap().len(), 20);
+}
+
+#[test]
+fn record_field_shouldReplayFieldsNoConfigDescribes() {
+    // walls and stuck seeds from a bitmap, walkers from the legend's green
+    let mut legend = BitmapLegend::new();
+    legend.set_color(0, 0, 0, BitmapCell::WALL);
+    let pbm = b"P1 12 10\n000000000000\n000000000000\n000000000000\n111110011111\n000000000000\n000000000000\n000000000000\n000000000000\n000000000000\n000000000000\n";
+    let mut field = DLAField::from_bitmap_seeded("test".to_string(), pbm, &legend, 3).unwrap();
+    let walkers: Vec<String> = (0..12).map(|x| format!(r#"{{ "x": {}, "y": 0, "state": "FREE", "sticky_neighbor": null, "color": {{ "r": 255, "g": 0, "b": 0, "a": 100 }} }}"#, x)).collect();
+    let mut json: serde_json::Value = serde_json::from_str(&field.to_json().unwrap()).unwrap();
+    json["agents"] = serde_json::from_str(&format!("[{}]", walkers.join(","))).unwrap();
+    json.as_object_mut().unwrap().remove("cells");
+    field = DLAField::from_json(&json.to_string()).unwrap();
+
+    let replay = Replay::record_field(&field, 40, 10).unwrap();
+    assert_eq!(replay.get_config(), None);
+
+    let regenerated = verify_replay(&replay.to_json().unwrap()).unwrap();
+    assert_eq!(regenerated.get_wall_count(), 10);
+    assert!(replay.get_ticks() > 0);
+    for _ in 0..replay.get_ticks() {
+        field.next_state();
+    }
+    assert_eq!(regenerated.export_npy_cells().unwrap(), field.export_npy_cells().unwrap());
+}
+
+#[test]
+fn replay_from_json_shouldNeedExactlyOneStart() {
+    let config = ReplayConfig::new("test".to_string(), 30, 20, 20, false, 2);
+    let json = Replay::record(&config, 10, 0).unwrap().to_json().unwrap();
+    let mut replay: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    // version 1 replays only ever had a config and still verify
+    replay["version"] = serde_json::json!(1);
+    assert!(verify_replay(&replay.to_string()).is_ok());
+
+    replay["start"] = serde_json::json!("RExBQw==");
+    assert!(Replay::from_json(&replay.to_string()).is_err());
+    replay.as_object_mut().unwrap().remove("config");
+    assert!(Replay::from_json(&replay.to_string()).is_err());
+    replay.as_object_mut().unwrap().remove("start");
+    assert!(Replay::from_json(&replay.to_string()).is_err());
+}
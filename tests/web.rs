@@ -14,15 +14,29 @@ fn get_ndx_shouldReturnTheCorrectIndex() {
     let ndx2 = DLAField::get_ndx(251, 89, 600);
     assert_eq!(ndx, 2404);
     assert_eq!(ndx2, 150689);
+
+    // columns are height cells long, not width
+    assert_eq!(DLAField::get_ndx(3, 2, 40), 122);
 }
 
 #[wasm_bindgen_test]
 fn new_shouldReturnANewFiled() {
-    let field = DLAField::new(60000, 600, 600);
+    let field = DLAField::new("test".to_string(), 60000, 600, 600).unwrap();
 }
 
 #[wasm_bindgen_test]
 fn nextState_shouldNotError() {
-    let mut field = DLAField::new(60000, 600, 600);
-    field.nextState();
+    let mut field = DLAField::new("test".to_string(), 60000, 600, 600).unwrap();
+    field.next_state();
+}
+
+#[wasm_bindgen_test]
+fn new_sparse_shouldOnlyAllocateTilesWithAgents() {
+    let mut field = DLAField::new_sparse("test".to_string(), 10, 60000, 60000).unwrap();
+    assert!(field.is_sparse());
+    assert!(field.get_allocated_cells() < 60000 * 60000);
+    assert_eq!(field.getOccpupiedCount(), 10);
+
+    field.next_state();
+    assert_eq!(field.getOccpupiedCount(), 10);
 }
@@ -1,6 +1,7 @@
 import {
   AgentState,
-  build_field_from_js_state, Color,
+  Autosave,
+  build_field_from_js_state, CanvasRenderer, Color,
   ColorizedPoint,
   DLAField,
   DLAFieldRenders,
@@ -70,6 +71,20 @@ canvases.forEach(canvas => {
   canvas.width = width
 })
 
+// looks up the canvas context once and only repaints cells listed in the field's change sets
+const renderer = new CanvasRenderer(canvas_id_1)
+field.set_track_changes(true)
+renderer.draw(field)
+field.take_changes().free()
+
+// snapshot every 500 ticks or every 250 newly stuck agents so a closed tab doesn't lose the run
+const autosave = Autosave.with_callback(500, 250, (snapshot, tick) => {
+  console.log(`autosave at tick ${tick}`)
+  saveSnapshotToLocalStorage(snapshot)
+})
+// run length encoded saves are a fraction of the size, DLAField.from_bytes reads them as well
+autosave.set_compressed(true)
+
 // const width = field.getWidth()
 // const height = field.getHeight()
 
@@ -77,7 +92,9 @@ canvases.forEach(canvas => {
 const renderLoop = () => {
   fields.forEach(field => {
     // Draw using Rust
-    DLAFieldRenders.draw(field, canvas_id_1)
+    const changes = field.take_changes()
+    renderer.draw_changes(changes)
+    changes.free()
     // DLAFieldRenders.draw(field, "dla-display-2")
 
     // Draw using JS
@@ -85,26 +102,26 @@ const renderLoop = () => {
 
     drawStickVersion(field, canvas_id_3)
 
-    if (field.next_state()) {
+    if (autosave.step(field)) {
       requestAnimationFrame(renderLoop)
     } else {
       // Completed render
       console.log(`completed render, longest tree: ' ${findTallest(field)}`)
 
       // save state
-      saveStateToLocalStorage(field)
+      autosave.save_now(field)
     }
   })
 }
 
 function runToCompleteThenRender() {
 
-  while (field.next_state()) {}
+  while (autosave.step(field)) {}
 
-  DLAFieldRenders.draw(field, "dla-display-1")
+  renderer.draw(field)
   draw(field, "dla-display-2")
 
-  saveStateToLocalStorage(field)
+  autosave.save_now(field)
 }
 
 function findTallest(field) {
@@ -224,69 +241,52 @@ function mapFromRangeToRange(valToMap, range1Start, range1End, range2Start, rang
       * Math.abs(range2End - range2Start) + range1Start;
 }
 
-function saveStateToLocalStorage(field) {
-  const stateToSave = []
-
-  const numAgents = field.get_num_agents();
-
-  for (let i = 0; i < numAgents; i++) {
-    const agent = field.get_agent_at(i);
-    const stickyNeighbor = agent.get_sticky_neighbor();
-    const stickyNeighborJS = !stickyNeighbor ? null : {
-      x: stickyNeighbor.x,
-      y: stickyNeighbor.y
-    }
-
-    const jsAgent = {
-      x: agent.get_x(),
-      y: agent.get_y(),
-      state: AgentState.STUCK,
-      color: {
-        r: agent.get_color().get_r(),
-        g: agent.get_color().get_g(),
-        b: agent.get_color().get_b(),
-        a: agent.get_color().get_a()
-      },
-      stickyNeighbor: stickyNeighborJS
-    }
-
-    stateToSave.push(jsAgent)
+function saveSnapshotToLocalStorage(bytes) {
+  // localStorage only holds strings, so base64 the snapshot
+  let binary = ''
+  for (let i = 0; i < bytes.length; i++) {
+    binary += String.fromCharCode(bytes[i])
   }
 
-  const itemToSave = JSON.stringify(stateToSave)
-  const itemToSaveCompressed = LZString.compress(itemToSave)
-
-  console.log(`set it to storage: ${itemToSave.length}`)
-  console.log(`compressed len: ${itemToSaveCompressed.length}`)
+  console.log(`snapshot len: ${bytes.length}`)
 
-  localStorage.setItem('last_state', itemToSaveCompressed)
+  localStorage.setItem('last_state_bin', btoa(binary))
 
   console.log('done')
 }
 
 function getStateFromLocalStorage() {
-  const itemStr = localStorage.getItem('last_state')
-  const decompressedItemStr = LZString.decompress(itemStr)
+  const snapshot = localStorage.getItem('last_state_bin')
 
-  const state = JSON.parse(decompressedItemStr)
+  if (!snapshot) {
+    // saves made before snapshots were added
+    return getLegacyStateFromLocalStorage()
+  }
 
-  const agents = state.map(item => {
-    const x = item.x
-    const y = item.y
+  const binary = atob(snapshot)
+  const bytes = new Uint8Array(binary.length)
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i)
+  }
 
-    const color = new Color(
-      item.color.r,
-      item.color.g,
-      item.color.b,
-      item.color.a
-    )
+  const newField = DLAField.from_bytes(bytes)
 
-    const neighbor = item.stickyNeighbor ? new StickyNeighbor(item.stickyNeighbor.x, item.stickyNeighbor.y) : null
+  draw(newField, canvas_id_2)
+}
 
-    return new ColorizedPoint(x, y, color, neighbor)
-  })
+function getLegacyStateFromLocalStorage() {
+  const itemStr = localStorage.getItem('last_state')
+  const decompressedItemStr = LZString.decompress(itemStr)
 
-  const newField = build_field_from_js_state(canvas_id_2, width, height, agents)
+  const agents = JSON.parse(decompressedItemStr)
 
-  draw(newField, canvas_id_2)
-}
\ No newline at end of file
+  // the saved agents are handed over as they are, Rust validates them and throws with the path
+  // of anything that doesn't fit, e.g. "agents[3].sticky_neighbor.x: missing"
+  try {
+    const newField = build_field_from_js_state(canvas_id_2, width, height, agents)
+
+    draw(newField, canvas_id_2)
+  } catch (e) {
+    console.error(`could not restore state: ${e}`)
+  }
+}